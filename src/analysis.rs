@@ -0,0 +1,375 @@
+//! This module runs the whole pipeline on a shader container held in memory:
+//! parsing the container, compiling each stage, decoding the AMDIL and analysing the dependencies.
+//!
+//! [analyze_fxo] never panics on bad input. Anything which goes wrong is recorded in the returned [FxoAnalysis],
+//! so one broken stage doesn't hide the results for the others.
+
+use std::{
+    any::Any,
+    panic::{catch_unwind, AssertUnwindSafe},
+    time::{Duration, Instant},
+};
+
+use turnip_gfx_disasm::amdil_text::{AMDILErrorContext, AMDILProgram};
+
+use crate::{
+    compile::{compile_dxbc, CompileError, Compiler},
+    db::{AsicTarget, ShaderStage},
+    disasm::{disassemble_amdil_text, output_dependencies_report},
+    dxbc::{ProgramVersion, ShaderInterface},
+    yk::{parse_container, YkContainerKind, YkGfxError},
+};
+
+/// The part of the pipeline a shader failed in, in pipeline order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// Parsing the GSFX/GSVS/GSPS container
+    Container,
+    /// Finding the shader bytecode inside the DXBC
+    DxbcExtraction,
+    /// Compiling the DXBC with atidxx64.dll
+    Compile,
+    /// Decoding the AMDIL text
+    Disassemble,
+    /// Dependency analysis of the decoded program
+    Analysis,
+}
+impl Phase {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Phase::Container => "container",
+            Phase::DxbcExtraction => "dxbc extraction",
+            Phase::Compile => "compile",
+            Phase::Disassemble => "disassemble",
+            Phase::Analysis => "analysis",
+        }
+    }
+}
+
+/// Why a stage couldn't be fully analysed
+#[derive(Debug)]
+pub enum StageError {
+    /// The bytecode couldn't be extracted, or the compiler failed
+    Compile(CompileError),
+    /// The compiler output couldn't be decoded
+    Disassemble(AMDILErrorContext),
+    /// The decoder or the analysis panicked
+    Panicked { phase: Phase, message: String },
+}
+impl StageError {
+    /// The phase the stage failed in
+    pub fn phase(&self) -> Phase {
+        match self {
+            StageError::Compile(CompileError::BytecodeExtraction { .. }) => Phase::DxbcExtraction,
+            StageError::Compile(_) => Phase::Compile,
+            StageError::Disassemble(_) => Phase::Disassemble,
+            StageError::Panicked { phase, .. } => *phase,
+        }
+    }
+}
+impl std::fmt::Display for StageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StageError::Compile(e) => write!(f, "couldn't compile: {e}"),
+            StageError::Disassemble(e) => write!(f, "couldn't disassemble: {e:?}"),
+            StageError::Panicked { phase, message } => write!(f, "panicked during {}: {message}", phase.to_str()),
+        }
+    }
+}
+
+/// The results for a single shader stage.
+///
+/// Each field is filled in as far as the pipeline got before [StageAnalysis::error].
+pub struct StageAnalysis {
+    pub stage: ShaderStage,
+    /// The signatures and reflection data from the DXBC
+    pub interface: ShaderInterface,
+    /// The AMDIL disassembly from the compiler
+    pub amdil: Option<String>,
+    pub program: Option<AMDILProgram>,
+    /// The report printed by [crate::disasm::print_output_depedencies]
+    pub dependencies: Option<String>,
+    pub error: Option<StageError>,
+    pub compile_time: Duration,
+    /// Time spent decoding and analysing the AMDIL
+    pub analysis_time: Duration,
+}
+
+/// A shader as described by its container
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShaderHeader {
+    /// The stage claimed by the container magic
+    pub container_stage: ShaderStage,
+    /// The version token of the DXBC program, if it could be read
+    pub program: Option<ProgramVersion>,
+}
+
+/// The parsed header of a shader container
+#[derive(Debug, Clone)]
+pub struct FxoHeader {
+    pub kind: YkContainerKind,
+    pub shaders: Vec<ShaderHeader>,
+}
+
+/// The results of [analyze_fxo]
+pub struct FxoAnalysis {
+    /// None if the container couldn't be parsed, in which case `container_error` says why and there are no stages
+    pub header: Option<FxoHeader>,
+    pub container_error: Option<String>,
+    /// Discrepancies between the container and the DXBC inside it
+    pub warnings: Vec<String>,
+    pub stages: Vec<StageAnalysis>,
+}
+impl FxoAnalysis {
+    /// True if the container was parsed and every stage was analysed without errors
+    pub fn succeeded(&self) -> bool {
+        self.container_error.is_none() && self.stages.iter().all(|stage| stage.error.is_none())
+    }
+}
+
+/// Extracts the message from a panic payload
+pub fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(v) => *v,
+        Err(e) => match e.downcast::<&str>() {
+            Ok(v) => v.to_string(),
+            _ => "Unknown Source of Error".to_owned()
+        }
+    }
+}
+
+/// Describes why a container couldn't be parsed
+fn describe_container_error(bytes: &[u8], error: nom::Err<YkGfxError<&[u8]>>) -> String {
+    match error {
+        nom::Err::Incomplete(_) => "couldn't parse shader container: truncated".to_owned(),
+        nom::Err::Error(YkGfxError::Nom(input, kind)) | nom::Err::Failure(YkGfxError::Nom(input, kind)) => {
+            format!(
+                "couldn't parse shader container: {} at offset {:#x}",
+                kind.description(),
+                bytes.len() - input.len()
+            )
+        }
+    }
+}
+
+/// Compiles, decodes and analyses one stage
+pub fn analyze_stage(compiler: &Compiler, stage: ShaderStage, dxbc: &[u8], target: AsicTarget) -> StageAnalysis {
+    let mut result = StageAnalysis {
+        stage,
+        interface: ShaderInterface::from_dxbc(dxbc, stage),
+        amdil: None,
+        program: None,
+        dependencies: None,
+        error: None,
+        compile_time: Duration::ZERO,
+        analysis_time: Duration::ZERO,
+    };
+
+    let start = Instant::now();
+    let compiled = compile_dxbc(compiler, dxbc, target).and_then(|compiled| Ok(compiled.amdil_disassembly()?.to_vec()));
+    result.compile_time = start.elapsed();
+    let amdil = match compiled {
+        Ok(amdil) => amdil,
+        Err(e) => {
+            result.error = Some(StageError::Compile(e));
+            return result;
+        }
+    };
+    result.amdil = Some(String::from_utf8_lossy(&amdil).into_owned());
+
+    // The decoder and analysis can panic on unexpected input
+    let start = Instant::now();
+    match catch_unwind(|| disassemble_amdil_text(&amdil)) {
+        Ok(Ok(program)) => {
+            let dependencies = catch_unwind(AssertUnwindSafe(|| output_dependencies_report(&program, Some(&result.interface))));
+            match dependencies {
+                Ok(dependencies) => result.dependencies = Some(dependencies),
+                Err(e) => {
+                    result.error = Some(StageError::Panicked {
+                        phase: Phase::Analysis,
+                        message: panic_message(e),
+                    })
+                }
+            }
+            result.program = Some(program);
+        }
+        Ok(Err(e)) => result.error = Some(StageError::Disassemble(e)),
+        Err(e) => {
+            result.error = Some(StageError::Panicked {
+                phase: Phase::Disassemble,
+                message: panic_message(e),
+            })
+        }
+    }
+    result.analysis_time = start.elapsed();
+    result
+}
+
+/// Pixel shader inputs which the rasterizer provides, rather than the vertex shader
+const RASTERIZER_INPUTS: [&str; 5] = ["SV_IsFrontFace", "SV_SampleIndex", "SV_PrimitiveID", "SV_Coverage", "SV_InnerCoverage"];
+
+/// Links the outputs of a vertex shader to the inputs of the pixel shader it's paired with, matching them by semantic
+/// like D3D does, and describes each pixel shader input which the vertex shader doesn't write or writes somewhere else
+pub fn link_vertex_to_pixel(vs: &ShaderInterface, ps: &ShaderInterface) -> Vec<String> {
+    let mut problems = vec![];
+    for input in &ps.inputs {
+        if RASTERIZER_INPUTS.iter().any(|name| input.semantic_name.eq_ignore_ascii_case(name)) {
+            continue;
+        }
+        let output = vs.outputs.iter().find(|output| {
+            output.semantic_name.eq_ignore_ascii_case(&input.semantic_name) && output.semantic_index == input.semantic_index
+        });
+        let input_location = format!("{}.{}", input.register_name("v"), input.swizzle());
+        match output {
+            None => problems.push(format!(
+                "pixel shader input {} ({input_location}) isn't written by the vertex shader",
+                input.field_name()
+            )),
+            Some(output) if output.register != input.register || input.mask & !output.mask != 0 => problems.push(format!(
+                "pixel shader input {} ({input_location}) doesn't match the vertex shader output at {}.{}",
+                input.field_name(),
+                output.register_name("o"),
+                output.swizzle()
+            )),
+            Some(_) => {}
+        }
+    }
+    problems
+}
+
+/// Runs the whole pipeline on the bytes of a shader container (`.fxo`, `.vso`, `.pso`...), compiling for `target`
+pub fn analyze_fxo(bytes: &[u8], compiler: &Compiler, target: AsicTarget) -> FxoAnalysis {
+    let container = match parse_container(bytes) {
+        Ok((_, container)) => container,
+        Err(e) => {
+            return FxoAnalysis {
+                header: None,
+                container_error: Some(describe_container_error(bytes, e)),
+                warnings: vec![],
+                stages: vec![],
+            }
+        }
+    };
+
+    FxoAnalysis {
+        header: Some(FxoHeader {
+            kind: container.kind,
+            shaders: container
+                .shaders
+                .iter()
+                .map(|shader| ShaderHeader {
+                    container_stage: shader.container_stage,
+                    program: shader.program,
+                })
+                .collect(),
+        }),
+        container_error: None,
+        warnings: container.warnings(),
+        stages: container
+            .stages()
+            .into_iter()
+            .map(|(stage, dxbc)| analyze_stage(compiler, stage, dxbc, target))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        dxbc::{ComponentType, SignatureElement},
+        worker::Supervisor,
+    };
+
+    /// A compiler which is never asked to compile anything, because the containers don't parse
+    fn unused_compiler() -> Compiler {
+        let supervisor = Supervisor::new("false", vec![], Duration::from_secs(1));
+        Compiler::isolated(supervisor, std::env::current_exe().unwrap()).unwrap()
+    }
+
+    fn gs_shader_header(magic: &[u8; 4], dxbc_offset: u32, dxbc_len: u32) -> Vec<u8> {
+        let mut bytes = magic.to_vec();
+        for field in [0, 0, dxbc_len, 0, 0, dxbc_offset, dxbc_len] {
+            bytes.extend_from_slice(&u32::to_le_bytes(field));
+        }
+        bytes
+    }
+
+    #[test]
+    fn truncated_containers_are_errors() {
+        let compiler = unused_compiler();
+
+        let mut gsfx = b"GSFX".to_vec();
+        gsfx.extend_from_slice(&[0; 20]);
+        // The DXBC runs past the end of the file
+        let mut gsvs = gs_shader_header(b"GSVS", 32, 0x100);
+        gsvs.extend_from_slice(b"DXBC");
+        // The offset and length overflow when added
+        let gsps = gs_shader_header(b"GSPS", u32::MAX, 2);
+        // The vertex shader slot points past the end of the file
+        let mut gsfx_slots = b"GSFX".to_vec();
+        gsfx_slots.extend_from_slice(&[0; 44]);
+        for field in [0x1000, 0x20, 0, 0] {
+            gsfx_slots.extend_from_slice(&u32::to_le_bytes(field));
+        }
+
+        for bytes in [&gsfx[..], &gsvs, &gsps, &gsfx_slots, &gsvs[..10]] {
+            let analysis = analyze_fxo(bytes, &compiler, AsicTarget::RDNA2);
+            assert!(analysis.container_error.is_some());
+            assert!(analysis.header.is_none());
+            assert!(analysis.stages.is_empty());
+        }
+    }
+
+    fn element(semantic_name: &str, semantic_index: u32, register: u32, mask: u8) -> SignatureElement {
+        SignatureElement {
+            semantic_name: semantic_name.to_owned(),
+            semantic_index,
+            system_value: 0,
+            component_type: ComponentType::Float,
+            register,
+            mask,
+        }
+    }
+
+    fn interface(stage: ShaderStage, inputs: Vec<SignatureElement>, outputs: Vec<SignatureElement>) -> ShaderInterface {
+        ShaderInterface {
+            stage,
+            inputs,
+            outputs,
+            reflection: Default::default(),
+        }
+    }
+
+    #[test]
+    fn pixel_inputs_are_linked_to_vertex_outputs() {
+        let vs = interface(
+            ShaderStage::Vertex,
+            vec![],
+            vec![
+                element("SV_Position", 0, 0, 0b1111),
+                element("TEXCOORD", 0, 1, 0b0011),
+                element("COLOR", 0, 2, 0b0111),
+            ],
+        );
+        let ps = interface(
+            ShaderStage::Fragment,
+            vec![
+                element("SV_POSITION", 0, 0, 0b1111),
+                element("TEXCOORD", 0, 1, 0b0011),
+                element("TEXCOORD", 1, 1, 0b1100),
+                element("COLOR", 0, 2, 0b1111),
+                element("SV_IsFrontFace", 0, 3, 0b0001),
+            ],
+            vec![],
+        );
+
+        assert_eq!(
+            link_vertex_to_pixel(&vs, &ps),
+            [
+                "pixel shader input TEXCOORD1 (v1.zw) isn't written by the vertex shader",
+                "pixel shader input COLOR0 (v2.xyzw) doesn't match the vertex shader output at o2.xyz",
+            ]
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use clap::Parser;
+use yk_fxo_disasm::analysis::panic_message;
+use yk_fxo_disasm::compile::CompilerIdentity;
+use yk_fxo_disasm::db::{AsicTarget, BytesType, DisasmType, ShaderDb, ShaderFilter, ShaderStage};
+use yk_fxo_disasm::disasm::{disassemble_amdil_text, outputs_depending_on};
+use yk_fxo_disasm::dxbc::ShaderInterface;
+
+/// List the shaders in a ShaderDb whose outputs depend on an input,
+/// e.g. to find what's affected by changing a vertex attribute or material parameter
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Database path
+    #[clap(value_parser)]
+    db_path: PathBuf,
+
+    /// The input: a scalar (`v2.z`), a register (`v2`, `t3`),
+    /// or the name of a constant buffer variable, constant buffer or resource
+    #[clap(value_parser)]
+    input: String,
+
+    /// The GPU architecture whose disassembly should be analysed
+    #[clap(long, value_enum, default_value_t = AsicTarget::RDNA2)]
+    target: AsicTarget,
+
+    /// Only check categories matching this glob
+    #[clap(long, value_parser)]
+    category: Option<String>,
+
+    /// Only check shader names matching this glob
+    #[clap(long, value_parser)]
+    name: Option<String>,
+
+    /// Only check this stage (e.g. "Vertex")
+    #[clap(long, value_parser = parse_stage)]
+    stage: Option<ShaderStage>,
+
+    /// Only check disassembly from the compiler with this file version or SHA-256 prefix, as printed for each shader
+    #[clap(long, value_parser)]
+    compiler: Option<String>,
+}
+
+fn parse_stage(s: &str) -> Result<ShaderStage, String> {
+    ShaderStage::try_from(s)
+}
+
+type ShaderKey = (String, String, ShaderStage);
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let db = ShaderDb::open_read_only(&args.db_path)?;
+    let filter = ShaderFilter {
+        category: args.category.clone(),
+        name: args.name.clone(),
+        stage: args.stage,
+    };
+
+    // Constant buffer variables and resources are named in the reflection data, which is in the DXBC
+    let dxbcs: BTreeMap<ShaderKey, Vec<u8>> = db
+        .query_bytes(&filter)?
+        .into_iter()
+        .filter(|row| row.bytes_type == BytesType::DXBC)
+        .map(|row| ((row.category, row.shader_name, row.shader_stage), row.bytes))
+        .collect();
+
+    // A shader can have disassembly from several compilers, but should only be counted once
+    let mut checked = BTreeSet::new();
+    let mut affected = BTreeSet::new();
+    let mut failed = vec![];
+    for row in db.query_disasm(&filter)? {
+        if row.disasm_type != DisasmType::AMDIL || row.asic_target != args.target {
+            continue;
+        }
+        if let Some(query) = &args.compiler {
+            if !row.compiler.as_ref().is_some_and(|compiler| compiler.matches(query)) {
+                continue;
+            }
+        }
+        let key = (row.category, row.shader_name, row.shader_stage);
+        let compiler = row.compiler.as_ref().map_or("unknown compiler".to_owned(), CompilerIdentity::short_name);
+        let name = format!("{}/{} ({}, {compiler})", key.0, key.1, key.2.to_str());
+        checked.insert(key.clone());
+        let inputs = match dxbcs.get(&key) {
+            Some(dxbc) => ShaderInterface::from_dxbc(dxbc, key.2).resolve_input_name(&args.input),
+            None => vec![args.input.clone()],
+        };
+
+        // The disassembler and analysis can panic on unexpected input
+        let outputs = std::panic::catch_unwind(|| {
+            disassemble_amdil_text(row.disasm.as_bytes()).map(|program| outputs_depending_on(&program, &inputs))
+        });
+        match outputs {
+            Ok(Ok(outputs)) if outputs.is_empty() => {}
+            Ok(Ok(outputs)) => {
+                affected.insert(key);
+                println!("{name}: {}", outputs.join(", "));
+            }
+            Ok(Err(e)) => failed.push(format!("{name}: couldn't disassemble: {e:?}")),
+            Err(e) => failed.push(format!("{name}: panicked: {}", panic_message(e))),
+        }
+    }
+
+    println!("\n{} of {} shaders depend on {}", affected.len(), checked.len(), args.input);
+    if !failed.is_empty() {
+        println!("\nCouldn't analyse ({}):", failed.len());
+        for failure in failed {
+            println!("{failure}");
+        }
+    }
+    Ok(())
+}
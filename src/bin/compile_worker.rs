@@ -0,0 +1,22 @@
+use std::path::PathBuf;
+
+use amd_dx_gsa::Atidxx64;
+use clap::Parser;
+use yk_fxo_disasm::worker::run_worker;
+
+/// Compiles shaders sent over stdin, for running compilation in a separate process (see `disasm_many --isolate`)
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// DLL path
+    #[clap(long, value_parser, default_value = "assets/atidxx64.dll")]
+    dll_path: PathBuf,
+}
+
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+
+    let dll = unsafe { Atidxx64::try_load_lib_from(args.dll_path).expect("no library found") };
+
+    run_worker(&dll, std::io::stdin().lock(), std::io::stdout().lock())
+}
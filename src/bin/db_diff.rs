@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use clap::Parser;
+use similar::TextDiff;
+use yk_fxo_disasm::compile::CompilerIdentity;
+use yk_fxo_disasm::db::{AsicTarget, BytesType, DisasmType, ShaderDb, ShaderFilter, ShaderStage};
+
+/// Compare the shaders stored in two ShaderDbs
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// The database to compare against (e.g. before a game patch)
+    #[clap(value_parser)]
+    old_db_path: PathBuf,
+
+    /// The database to compare (e.g. after a game patch)
+    #[clap(value_parser)]
+    new_db_path: PathBuf,
+
+    /// Only list the changed shaders, don't diff their disassembly
+    #[clap(long, value_parser)]
+    no_disasm_diff: bool,
+
+    /// The GPU architecture whose disassembly should be diffed
+    #[clap(long, value_enum, default_value_t = AsicTarget::RDNA2)]
+    target: AsicTarget,
+}
+
+type ShaderKey = (String, String, ShaderStage);
+
+fn key_to_string((category, shader_name, shader_stage): &ShaderKey) -> String {
+    format!("{category}/{shader_name} ({})", shader_stage.to_str())
+}
+
+/// Maps each shader in the database to the SHA-256 of its DXBC
+fn dxbc_hashes(db: &ShaderDb) -> anyhow::Result<BTreeMap<ShaderKey, Vec<u8>>> {
+    Ok(db
+        .query_bytes(&ShaderFilter::default())?
+        .into_iter()
+        .filter(|row| row.bytes_type == BytesType::DXBC)
+        .map(|row| ((row.category, row.shader_name, row.shader_stage), row.sha256))
+        .collect())
+}
+
+/// A disassembly, and the compiler which produced it
+type Disasm = (String, Option<CompilerIdentity>);
+
+/// Maps each shader in the database to its AMDIL disassemblies for the given target, one for each compiler which produced one
+fn amdil_disasms(db: &ShaderDb, target: AsicTarget) -> anyhow::Result<BTreeMap<ShaderKey, Vec<Disasm>>> {
+    let mut disasms: BTreeMap<ShaderKey, Vec<Disasm>> = BTreeMap::new();
+    for row in db.query_disasm(&ShaderFilter::default())? {
+        if row.disasm_type == DisasmType::AMDIL && row.asic_target == target {
+            disasms
+                .entry((row.category, row.shader_name, row.shader_stage))
+                .or_default()
+                .push((row.disasm, row.compiler));
+        }
+    }
+    Ok(disasms)
+}
+
+fn describe_compiler(compiler: &Option<CompilerIdentity>) -> String {
+    compiler.as_ref().map_or("an unknown compiler".to_owned(), CompilerIdentity::short_name)
+}
+
+/// Pairs up the old and new disassemblies of a shader which were produced by the same compiler.
+/// If neither database has more than one, they are paired even if the compilers differ.
+fn pair_disasms<'a>(old: &'a [Disasm], new: &'a [Disasm]) -> Vec<(&'a Disasm, &'a Disasm)> {
+    if let ([old], [new]) = (old, new) {
+        return vec![(old, new)];
+    }
+    let sha256 = |(_, compiler): &Disasm| compiler.as_ref().map(|compiler| compiler.sha256.clone());
+    new.iter()
+        .filter_map(|new| old.iter().find(|old| sha256(old) == sha256(new)).map(|old| (old, new)))
+        .collect()
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    let old_db = ShaderDb::open_read_only(&args.old_db_path)?;
+    let new_db = ShaderDb::open_read_only(&args.new_db_path)?;
+
+    let old_hashes = dxbc_hashes(&old_db)?;
+    let new_hashes = dxbc_hashes(&new_db)?;
+
+    let added: Vec<_> = new_hashes.keys().filter(|key| !old_hashes.contains_key(key)).collect();
+    let removed: Vec<_> = old_hashes.keys().filter(|key| !new_hashes.contains_key(key)).collect();
+    let changed: Vec<_> = new_hashes
+        .iter()
+        .filter_map(|(key, new_hash)| match old_hashes.get(key) {
+            Some(old_hash) if old_hash != new_hash => Some(key),
+            _ => None,
+        })
+        .collect();
+
+    println!("Added ({}):", added.len());
+    for key in &added {
+        println!("\t{}", key_to_string(key));
+    }
+    println!("\nRemoved ({}):", removed.len());
+    for key in &removed {
+        println!("\t{}", key_to_string(key));
+    }
+    println!("\nChanged ({}):", changed.len());
+    for key in &changed {
+        println!("\t{}", key_to_string(key));
+    }
+
+    if args.no_disasm_diff || changed.is_empty() {
+        return Ok(());
+    }
+
+    let old_disasms = amdil_disasms(&old_db, args.target)?;
+    let new_disasms = amdil_disasms(&new_db, args.target)?;
+
+    for key in changed {
+        let name = key_to_string(key);
+        println!("\n{name}");
+        let old = old_disasms.get(key).map_or(&[][..], Vec::as_slice);
+        let new = new_disasms.get(key).map_or(&[][..], Vec::as_slice);
+        let pairs = pair_disasms(old, new);
+        if pairs.is_empty() {
+            println!("{} AMDIL disassembly from the same compiler missing from one or both databases", args.target.to_str());
+        }
+        for ((old_disasm, old_compiler), (new_disasm, new_compiler)) in pairs {
+            if old_compiler != new_compiler {
+                println!(
+                    "Note: compiled by {} in the old database and {} in the new database",
+                    describe_compiler(old_compiler),
+                    describe_compiler(new_compiler)
+                );
+            }
+            let diff = TextDiff::from_lines(old_disasm, new_disasm);
+            print!(
+                "{}",
+                diff.unified_diff().header(
+                    &format!("{} {name} ({})", args.old_db_path.display(), describe_compiler(old_compiler)),
+                    &format!("{} {name} ({})", args.new_db_path.display(), describe_compiler(new_compiler))
+                )
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -1,16 +1,16 @@
-use std::collections::HashMap;
 use std::io::Write;
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 use amd_dx_gsa::Atidxx64;
 use clap::Parser;
-use yk_fxo_disasm::disasm::{print_output_depedencies, analyze_program};
-use yk_fxo_disasm::yk::parse_gsfx;
+use yk_fxo_disasm::analysis::{analyze_fxo, link_vertex_to_pixel, StageAnalysis, StageError};
+use yk_fxo_disasm::db::{AsicTarget, ShaderStage};
+use yk_fxo_disasm::report::{cluster_failures, failures_by_phase, write_html_report, write_jsonl, write_junit, StageOutcome, StageResult, UnitResult};
+use yk_fxo_disasm::source::{group_shader_files, read_shader_files, ShaderUnit};
+use yk_fxo_disasm::worker::Supervisor;
 
-use yk_fxo_disasm::{
-    compile::compile_dxbc_to_amdil_text,
-    disasm::disassemble_amdil_text,
-};
+use yk_fxo_disasm::compile::{CompileError, Compiler};
 
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
@@ -20,100 +20,249 @@ struct Args {
     #[clap(long, value_parser, default_value = "assets/atidxx64.dll")]
     dll_path: PathBuf,
 
-    /// Shader path
+    /// Shader directory, or a .par archive containing shaders.
+    /// .fxo, .vso and .pso files are detected by their contents, and .vso/.pso files with the same name are analysed together.
     #[clap(value_parser)]
     fxo_dir: PathBuf,
 
     #[clap(value_parser)]
     report_path: PathBuf,
+
+    /// The GPU architecture to compile for
+    #[clap(long, value_enum, default_value_t = AsicTarget::RDNA2)]
+    target: AsicTarget,
+
+    /// Directory to cache compiler output in, so unchanged shaders aren't recompiled on later runs
+    #[clap(long, value_parser)]
+    cache_dir: Option<PathBuf>,
+
+    /// Compile in a separate compile_worker process, so shaders which crash or hang the compiler don't stop the run
+    #[clap(long, value_parser)]
+    isolate: bool,
+
+    /// With --isolate, how long a compilation may take before the worker is killed
+    #[clap(long, value_parser, default_value_t = 60)]
+    timeout_secs: u64,
+
+    /// Also write a browsable HTML report into this directory, including the disassembly and dependency report for each shader
+    #[clap(long, value_parser)]
+    html_report: Option<PathBuf>,
+
+    /// Also write one JSON record per file to this path, as JSON Lines
+    #[clap(long, value_parser)]
+    jsonl: Option<PathBuf>,
+
+    /// Also write a JUnit XML file to this path, with each shader as a test case
+    #[clap(long, value_parser)]
+    junit: Option<PathBuf>,
 }
 
-fn read_fxo(dll: &Atidxx64, fxo_path: std::path::PathBuf) {
-    let fxo = std::fs::read(fxo_path).expect("couldn't read fxo file");
+/// Converts an analysed stage for the report.
+///
+/// If `keep_text` is set, the AMDIL and dependency report are kept for the HTML report.
+fn stage_result(analysis: StageAnalysis, keep_text: bool) -> StageResult {
+    let (outcome, phase, message) = match &analysis.error {
+        None => (StageOutcome::Success, None, None),
+        Some(e) => {
+            let outcome = match e {
+                StageError::Compile(CompileError::WorkerCrashed { .. }) => StageOutcome::Crashed,
+                StageError::Compile(CompileError::WorkerTimedOut { .. }) => StageOutcome::TimedOut,
+                _ => StageOutcome::Failed,
+            };
+            (outcome, Some(e.phase()), Some(format!("{} shader {e}", analysis.stage.to_str())))
+        }
+    };
+    StageResult {
+        stage: analysis.stage,
+        outcome,
+        phase,
+        message,
+        amdil: analysis.amdil.filter(|_| keep_text),
+        dependencies: analysis.dependencies.filter(|_| keep_text),
+        compile_time: analysis.compile_time,
+        analysis_time: analysis.analysis_time,
+    }
+}
 
-    let (_, (gsvs, gsps)) = parse_gsfx(&fxo).expect("couldn't parse fxo file");
+/// The outcome of the stage the unit failed in, or None if it succeeded or failed before reaching any stage
+fn failed_outcome(result: &UnitResult) -> Option<StageOutcome> {
+    if result.error.is_some() {
+        return None;
+    }
+    result.first_failed_stage().map(|stage| stage.outcome)
+}
 
-    let vert_program = compile_dxbc_to_amdil_text(dll, gsvs.dxbc, disassemble_amdil_text)
-        .expect("couldn't compile vertex shader")
-        .expect("couldn't disassemble vertex shader");
-    let frag_program = compile_dxbc_to_amdil_text(dll, gsps.dxbc, disassemble_amdil_text)
-        .expect("couldn't compile frag shader")
-        .expect("couldn't disassemble frag shader");
+/// Where a unit failed, e.g. `compile, Vertex`
+fn failure_location(result: &UnitResult) -> String {
+    let Some(phase) = result.failure_phase() else {
+        return String::new();
+    };
+    match result.first_failed_stage().filter(|_| result.error.is_none()) {
+        Some(stage) => format!("{}, {}", phase.to_str(), stage.stage.to_str()),
+        None => phase.to_str().to_owned(),
+    }
+}
 
-    analyze_program(&vert_program);
-    analyze_program(&frag_program);
+/// Compiles, disassembles and analyses every shader in the unit
+fn read_unit(compiler: &Compiler, unit: &ShaderUnit, target: AsicTarget, keep_text: bool) -> UnitResult {
+    let start = Instant::now();
+    let mut result = UnitResult {
+        name: unit.name(),
+        paths: unit.files().iter().map(|file| file.path.clone()).collect(),
+        warnings: vec![],
+        error: None,
+        stages: vec![],
+        duration: Duration::ZERO,
+    };
+    let mut interfaces = vec![];
+    for file in unit.files() {
+        let analysis = analyze_fxo(&file.data, compiler, target);
+        if let Some(error) = analysis.container_error {
+            // Name the file, as a unit can have more than one
+            result.error = Some(format!("{error} ('{}')", file.path));
+            break;
+        }
+        result.warnings.extend(analysis.warnings);
+        interfaces.extend(analysis.stages.iter().map(|stage| stage.interface.clone()));
+        result
+            .stages
+            .extend(analysis.stages.into_iter().map(|stage| stage_result(stage, keep_text)));
+    }
+    if let ShaderUnit::Pair { .. } = unit {
+        let find = |stage| interfaces.iter().find(|interface| interface.stage == stage);
+        if let (Some(vs), Some(ps)) = (find(ShaderStage::Vertex), find(ShaderStage::Fragment)) {
+            result.warnings.extend(link_vertex_to_pixel(vs, ps));
+        }
+    }
+    result.duration = start.elapsed();
+    result
 }
 
 fn main() {
     let args = Args::parse();
 
-    let dll = unsafe { Atidxx64::try_load_lib_from(args.dll_path).expect("no library found") };
-
-    let fxos = std::fs::read_dir(args.fxo_dir)
-        .expect("couldn't open directory")
-        .filter_map(|file| {
-            match file {
-                Ok(file) => {
-                    if file.file_name().to_str()?.ends_with(".fxo") {
-                        Some(file)
-                    } else {
-                        None
-                    }
-                },
-                Err(_) => None,
-            }
-        });
+    let mut compiler = if args.isolate {
+        // compile_worker is built alongside this binary
+        let worker_path = std::env::current_exe()
+            .expect("couldn't find current executable")
+            .with_file_name(format!("compile_worker{}", std::env::consts::EXE_SUFFIX));
+        let supervisor = Supervisor::new(
+            worker_path,
+            vec!["--dll-path".to_owned(), args.dll_path.to_string_lossy().into_owned()],
+            Duration::from_secs(args.timeout_secs),
+        );
+        Compiler::isolated(supervisor, &args.dll_path).expect("couldn't fingerprint library")
+    } else {
+        let dll = unsafe { Atidxx64::try_load_lib_from(&args.dll_path).expect("no library found") };
+        Compiler::new(dll, &args.dll_path).expect("couldn't fingerprint library")
+    };
+    if let Some(cache_dir) = &args.cache_dir {
+        compiler = compiler.with_cache_dir(cache_dir).expect("couldn't create cache directory");
+    }
+
+    let files = read_shader_files(&args.fxo_dir, |_| true).expect("couldn't read shader files");
+    let units = group_shader_files(files.files);
+
+    let mut results: Vec<UnitResult> = units
+        .iter()
+        .map(|unit| read_unit(&compiler, unit, args.target, args.html_report.is_some()))
+        .collect();
+    // Files which couldn't be read are reported like containers which couldn't be parsed
+    results.extend(files.failures.into_iter().map(|failure| UnitResult {
+        name: failure.path.clone(),
+        paths: vec![failure.path],
+        warnings: vec![],
+        error: Some(format!("couldn't read file: {}", failure.error)),
+        stages: vec![],
+        duration: Duration::ZERO,
+    }));
+
+    let mut summary_lines = vec![format!("Compiler: {} ({})", compiler.identity().short_name(), compiler.identity().path)];
+    if let Some(stats) = compiler.cache_stats() {
+        summary_lines.push(format!("Compile cache: {} hits, {} misses", stats.hits, stats.misses));
+    }
 
     let mut successes = vec![];
-    let mut failures: HashMap<String, Vec<String>> = HashMap::new();
-    let mut total_files = 0;
-
-    for file in fxos {
-        total_files += 1;
-        let file_path = file.path();
-        let file_name = file.file_name().to_str().unwrap().to_owned();
-        let res = std::panic::catch_unwind(|| {
-            read_fxo(&dll, file_path);
-        });
-        match res {
-            Ok(_) => {
-                // report.write_fmt(format_args!("{file_name}\nSUCCESS\n\n")).unwrap();
-                successes.push(file_name);
-            },
-            Err(e) => {
-                let err_msg = {
-                    match e.downcast::<String>() {
-                        Ok(v) => *v,
-                        Err(e) => match e.downcast::<&str>() {
-                            Ok(v) => v.to_string(),
-                            _ => "Unknown Source of Error".to_owned()
-                        }
-                    }
-                };
-
-                // report.write_fmt(format_args!("{file_name}\nFAILURE\n{err_msg}\n\n")).unwrap();
-                match failures.get_mut(&err_msg) {
-                    Some(associated_files) => associated_files.push(file_name),
-                    None => {
-                        failures.insert(err_msg, vec![file_name]);
-                    }
-                }
-            },
+    let mut warnings = vec![];
+    let mut crashed = vec![];
+    let mut timed_out = vec![];
+    let total_files = results.len();
+
+    for result in &results {
+        let file_name = result.name.clone();
+        warnings.extend(result.warnings.iter().map(|warning| format!("{file_name}: {warning}")));
+        match (result.failure_message(), failed_outcome(result)) {
+            (None, _) => successes.push(file_name),
+            (Some(message), Some(StageOutcome::Crashed)) => crashed.push(format!("{file_name} [{}]: {message}", failure_location(result))),
+            (Some(message), Some(StageOutcome::TimedOut)) => timed_out.push(format!("{file_name} [{}]: {message}", failure_location(result))),
+            // Other failures are clustered below
+            (Some(_), _) => {},
         }
     }
 
     let mut report = std::fs::File::create(args.report_path).expect("couldn't open report file");
-    report.write_fmt(format_args!("\n\nSUMMARY\nSuccesses: {} out of {}\n\n", successes.len(), total_files)).unwrap();
+    report.write_fmt(format_args!("\n\nSUMMARY\n{}\n", summary_lines[0])).unwrap();
+    report.write_fmt(format_args!("Successes: {} out of {}\n", successes.len(), total_files)).unwrap();
+    for line in &summary_lines[1..] {
+        report.write_fmt(format_args!("{line}\n")).unwrap();
+    }
+    let phases = failures_by_phase(&results);
+    if !phases.is_empty() {
+        report.write_fmt(format_args!("Failures by phase:\n")).unwrap();
+    }
+    for failures in phases {
+        let stages: Vec<String> = failures
+            .stages
+            .iter()
+            .map(|(stage, count)| format!("{}: {count}", stage.to_str()))
+            .collect();
+        if stages.is_empty() {
+            report.write_fmt(format_args!("  {}: {}\n", failures.phase.to_str(), failures.units)).unwrap();
+        } else {
+            report.write_fmt(format_args!("  {}: {} ({})\n", failures.phase.to_str(), failures.units, stages.join(", "))).unwrap();
+        }
+    }
+    report.write_fmt(format_args!("\n")).unwrap();
     for file_name in successes {
         report.write_fmt(format_args!("{file_name}\n")).unwrap();
     }
-    report.write_fmt(format_args!("\nFailures ({} unique):\n", failures.len())).unwrap();
-    let mut failures = failures.into_iter().collect::<Vec<_>>();
-    failures.sort_by_cached_key(|(err_msg, _file_names)| err_msg.chars().rev().collect::<String>());
-    for (err_msg, file_names) in failures {
-        report.write_fmt(format_args!("\n{err_msg}\n")).unwrap();
-        for file_name in file_names {
-            report.write_fmt(format_args!("{file_name}\n")).unwrap();   
+    report.write_fmt(format_args!("\nWarnings ({}):\n", warnings.len())).unwrap();
+    for warning in warnings {
+        report.write_fmt(format_args!("{warning}\n")).unwrap();
+    }
+    report.write_fmt(format_args!("\nCrashed ({}):\n", crashed.len())).unwrap();
+    for crash in crashed {
+        report.write_fmt(format_args!("{crash}\n")).unwrap();
+    }
+    report.write_fmt(format_args!("\nTimed out ({}):\n", timed_out.len())).unwrap();
+    for timeout in timed_out {
+        report.write_fmt(format_args!("{timeout}\n")).unwrap();
+    }
+    // Crashes and timeouts are listed above, so only cluster the other failures
+    let clusters = cluster_failures(
+        results
+            .iter()
+            .enumerate()
+            .filter(|(_, result)| !matches!(failed_outcome(result), Some(StageOutcome::Crashed | StageOutcome::TimedOut))),
+    );
+    let unique_messages: usize = clusters.iter().map(|cluster| cluster.unique_messages).sum();
+    report.write_fmt(format_args!("\nFailures ({} clusters, {unique_messages} unique messages):\n", clusters.len())).unwrap();
+    for cluster in clusters {
+        report.write_fmt(format_args!("\n[{}] {}\ne.g. {}\n", cluster.units.len(), cluster.template, cluster.example)).unwrap();
+        for index in cluster.units {
+            report.write_fmt(format_args!("{} [{}]\n", results[index].name, failure_location(&results[index]))).unwrap();
         }
     }
+
+    if let Some(html_report) = &args.html_report {
+        write_html_report(html_report, "disasm_many report", &summary_lines, &results).expect("couldn't write HTML report");
+    }
+    if let Some(jsonl) = &args.jsonl {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(jsonl).expect("couldn't open JSON Lines file"));
+        write_jsonl(&mut file, &results).expect("couldn't write JSON Lines file");
+    }
+    if let Some(junit) = &args.junit {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(junit).expect("couldn't open JUnit file"));
+        write_junit(&mut file, "disasm_many", &results).expect("couldn't write JUnit file");
+    }
 }
@@ -0,0 +1,184 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use serde::Serialize;
+use yk_fxo_disasm::compile::CompilerIdentity;
+use yk_fxo_disasm::db::{sha256, AsicTarget, BytesType, DisasmType, ShaderDb, ShaderFilter, ShaderStage};
+
+/// Export shaders from a ShaderDb into a directory tree
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Database path
+    #[clap(value_parser)]
+    db_path: PathBuf,
+
+    /// Directory to export into
+    #[clap(value_parser)]
+    out_dir: PathBuf,
+
+    /// Only export categories matching this glob
+    #[clap(long, value_parser)]
+    category: Option<String>,
+
+    /// Only export shader names matching this glob
+    #[clap(long, value_parser)]
+    name: Option<String>,
+
+    /// Only export this stage (e.g. "Vertex")
+    #[clap(long, value_parser = parse_stage)]
+    stage: Option<ShaderStage>,
+}
+
+fn parse_stage(s: &str) -> Result<ShaderStage, String> {
+    ShaderStage::try_from(s)
+}
+
+/// An entry in `manifest.json`, describing one exported file
+#[derive(Serialize)]
+struct ManifestEntry {
+    category: String,
+    shader_name: String,
+    shader_stage: &'static str,
+    kind: &'static str,
+    /// The architecture compiler output was generated for
+    asic_target: Option<&'static str>,
+    /// The file version of the DLL which produced the file, if known
+    compiler_version: Option<String>,
+    /// The SHA-256 of the DLL which produced the file, if known
+    compiler_sha256: Option<String>,
+    path: String,
+    sha256: String,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn bytes_extension(bytes_type: BytesType) -> &'static str {
+    match bytes_type {
+        BytesType::DXBC => "dxbc",
+        BytesType::ELF => "elf",
+    }
+}
+
+fn disasm_extension(disasm_type: DisasmType) -> &'static str {
+    match disasm_type {
+        DisasmType::AMDIL => "amdil.txt",
+    }
+}
+
+/// Makes a category or shader name from the DB safe to use as a single path component,
+/// so a name like `../x` or `/x` can't write outside of `out_dir`
+fn sanitize_component(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if matches!(c, '/' | '\\' | ':' | '\0') { '_' } else { c })
+        .collect();
+    if name.is_empty() || name.chars().all(|c| c == '.') {
+        format!("_{name}")
+    } else {
+        name
+    }
+}
+
+/// Writes `data` to `out_dir/category/name.stage[.target][.compiler].extension`, returning the path relative to `out_dir`.
+/// The compiler is the start of the DLL's SHA-256, so output from different DLLs doesn't collide.
+/// The category and name are passed through [sanitize_component] first.
+#[allow(clippy::too_many_arguments)]
+fn write_shader_file(
+    out_dir: &Path,
+    category: &str,
+    shader_name: &str,
+    shader_stage: ShaderStage,
+    asic_target: Option<AsicTarget>,
+    compiler: Option<&CompilerIdentity>,
+    extension: &str,
+    data: &[u8],
+) -> anyhow::Result<String> {
+    let target = asic_target
+        .map(|target| format!(".{}", target.to_str().to_lowercase()))
+        .unwrap_or_default();
+    let compiler = compiler
+        .map(|compiler| format!(".{}", &to_hex(&compiler.sha256)[..16]))
+        .unwrap_or_default();
+    let relative_path = format!(
+        "{}/{}.{}{target}{compiler}.{extension}",
+        sanitize_component(category),
+        sanitize_component(shader_name),
+        shader_stage.short_name()
+    );
+    let path = out_dir.join(&relative_path);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(path, data)?;
+    Ok(relative_path)
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    let db = ShaderDb::open_read_only(args.db_path)?;
+    let filter = ShaderFilter {
+        category: args.category,
+        name: args.name,
+        stage: args.stage,
+    };
+
+    let mut manifest = vec![];
+
+    for row in db.query_bytes(&filter)? {
+        let path = write_shader_file(
+            &args.out_dir,
+            &row.category,
+            &row.shader_name,
+            row.shader_stage,
+            row.asic_target,
+            row.compiler.as_ref(),
+            bytes_extension(row.bytes_type),
+            &row.bytes,
+        )?;
+        manifest.push(ManifestEntry {
+            category: row.category,
+            shader_name: row.shader_name,
+            shader_stage: row.shader_stage.to_str(),
+            kind: row.bytes_type.to_str(),
+            asic_target: row.asic_target.map(AsicTarget::to_str),
+            compiler_version: row.compiler.as_ref().and_then(|compiler| compiler.file_version.clone()),
+            compiler_sha256: row.compiler.as_ref().map(|compiler| to_hex(&compiler.sha256)),
+            path,
+            sha256: to_hex(&row.sha256),
+        });
+    }
+
+    for row in db.query_disasm(&filter)? {
+        let path = write_shader_file(
+            &args.out_dir,
+            &row.category,
+            &row.shader_name,
+            row.shader_stage,
+            Some(row.asic_target),
+            row.compiler.as_ref(),
+            disasm_extension(row.disasm_type),
+            row.disasm.as_bytes(),
+        )?;
+        manifest.push(ManifestEntry {
+            category: row.category,
+            shader_name: row.shader_name,
+            shader_stage: row.shader_stage.to_str(),
+            kind: row.disasm_type.to_str(),
+            asic_target: Some(row.asic_target.to_str()),
+            compiler_version: row.compiler.as_ref().and_then(|compiler| compiler.file_version.clone()),
+            compiler_sha256: row.compiler.as_ref().map(|compiler| to_hex(&compiler.sha256)),
+            path,
+            sha256: to_hex(&sha256(row.disasm.as_bytes())),
+        });
+    }
+
+    std::fs::create_dir_all(&args.out_dir)?;
+    let manifest_file = std::fs::File::create(args.out_dir.join("manifest.json"))?;
+    serde_json::to_writer_pretty(manifest_file, &manifest)?;
+
+    println!("Exported {} files", manifest.len());
+
+    Ok(())
+}
@@ -6,12 +6,13 @@ use std::path::PathBuf;
 use amd_dx_gsa::Atidxx64;
 use anyhow::anyhow;
 use clap::Parser;
-use yk_fxo_disasm::db::{ShaderDb, BytesType, ShaderStage, DisasmType, DbResult};
+use yk_fxo_disasm::db::{ShaderDb, BytesType, DisasmType, DbResult, AsicTarget};
 use yk_fxo_disasm::disasm::{print_output_depedencies, analyze_program};
-use yk_fxo_disasm::yk::{parse_gsfx, parse_gsvs, parse_gsps};
+use yk_fxo_disasm::source::{read_shader_files, ShaderFile};
+use yk_fxo_disasm::yk::{parse_container, YkContainerKind};
 
 use yk_fxo_disasm::{
-    compile::compile_dxbc_to_amdil_text,
+    compile::{compile_dxbc, Compiler},
     disasm::disassemble_amdil_text,
 };
 
@@ -23,7 +24,7 @@ struct Args {
     #[clap(long, value_parser, default_value = "assets/atidxx64.dll")]
     dll_path: PathBuf,
 
-    /// Shader path
+    /// Shader directory, or a .par archive containing shaders
     #[clap(value_parser)]
     fxo_dir: PathBuf,
 
@@ -32,108 +33,67 @@ struct Args {
 
     #[clap(value_parser)]
     db_path: PathBuf,
-}
-
-fn path_to_shader_name(path: &PathBuf) -> String {
-    path.file_name().unwrap().to_string_lossy().split_once(".").unwrap().0.to_string()
-}
-
-fn read_fxo(dll: &Atidxx64, category: &str, fxo_path: std::path::PathBuf, db: &mut ShaderDb) -> anyhow::Result<()> {
-    let shader_name = &path_to_shader_name(&fxo_path);
-    let fxo = std::fs::read(fxo_path)?;
-
-    let (_, (gsvs, gsps)) = parse_gsfx(&fxo).map_err(|e| anyhow!("Failed to parse FXO {e:?}"))?;
 
-    db.insert_bytes(category, shader_name, ShaderStage::Vertex, BytesType::DXBC, gsvs.dxbc)?;
-    db.insert_bytes(category, shader_name, ShaderStage::Fragment, BytesType::DXBC, gsps.dxbc)?;
-    
-    compile_dxbc_to_amdil_text(dll, gsvs.dxbc, |text| {
-        let disasm = std::str::from_utf8(text).unwrap();
-        db.insert_disasm(category, shader_name, ShaderStage::Vertex, DisasmType::AMDIL, disasm)
-    })??;
+    /// The GPU architectures to compile for. Can be passed multiple times to store the output for each one.
+    #[clap(long = "target", value_enum, default_values_t = [AsicTarget::RDNA2])]
+    targets: Vec<AsicTarget>,
 
-    compile_dxbc_to_amdil_text(dll, gsps.dxbc, |text| {
-        let disasm = std::str::from_utf8(text).unwrap();
-        db.insert_disasm(category, shader_name, ShaderStage::Fragment, DisasmType::AMDIL, disasm)
-    })??;
-
-    Ok(())
+    /// Directory to cache compiler output in, so unchanged shaders aren't recompiled on later runs
+    #[clap(long, value_parser)]
+    cache_dir: Option<PathBuf>,
 }
 
-fn read_vso(dll: &Atidxx64, category: &str, vso_path: std::path::PathBuf, db: &mut ShaderDb) -> anyhow::Result<()> {
-    let shader_name = &path_to_shader_name(&vso_path);
-    let vso = std::fs::read(vso_path)?;
-
-    let (_, gsvs) = parse_gsvs(&vso).map_err(|e| anyhow!("Failed to parse VSO {e:?}"))?;
+fn read_container(compiler: &Compiler, compiler_id: i64, category: &str, file: ShaderFile, targets: &[AsicTarget], db: &mut ShaderDb) -> anyhow::Result<()> {
+    let shader_name = &file.stem().to_owned();
 
-    db.insert_bytes(category, shader_name, ShaderStage::Vertex, BytesType::DXBC, gsvs.dxbc)?;
-    
-    compile_dxbc_to_amdil_text(dll, gsvs.dxbc, |text| {
-        let disasm = std::str::from_utf8(text).unwrap();
-        db.insert_disasm(category, shader_name, ShaderStage::Vertex, DisasmType::AMDIL, disasm)
-    })??;
+    let (_, container) = parse_container(&file.data).map_err(|e| anyhow!("Failed to parse {} {e:?}", file.path))?;
 
-    Ok(())
-}
+    for shader in container.shaders {
+        let stage = shader.stage();
+        let warnings = shader.warnings();
+        for warning in &warnings {
+            eprintln!("warning: {} ({}): {warning}", file.path, shader.container_stage.to_str());
+        }
 
-fn read_pso(dll: &Atidxx64, category: &str, pso_path: std::path::PathBuf, db: &mut ShaderDb) -> anyhow::Result<()> {
-    let shader_name = &path_to_shader_name(&pso_path);
-    let pso = std::fs::read(pso_path)?;
+        db.insert_bytes(category, shader_name, stage, BytesType::DXBC, shader.dxbc, !warnings.is_empty(), None, None)?;
 
-    let (_, gsps) = parse_gsps(&pso).map_err(|e| anyhow!("Failed to parse PSO {e:?}"))?;
+        for &target in targets {
+            let compiled = compile_dxbc(compiler, shader.dxbc, target)?;
+            db.insert_bytes(category, shader_name, stage, BytesType::ELF, &compiled.elf, false, Some(target), Some(compiler_id))?;
 
-    db.insert_bytes(category, shader_name, ShaderStage::Fragment, BytesType::DXBC, gsps.dxbc)?;
-    
-    compile_dxbc_to_amdil_text(dll, gsps.dxbc, |text| {
-        let disasm = std::str::from_utf8(text).unwrap();
-        db.insert_disasm(category, shader_name, ShaderStage::Fragment, DisasmType::AMDIL, disasm)
-    })??;
+            let disasm = std::str::from_utf8(compiled.amdil_disassembly()?)
+                .map_err(|e| anyhow!("{} {} shader disassembly isn't valid UTF-8: {e}", file.path, stage.to_str()))?;
+            db.insert_disasm(category, shader_name, stage, DisasmType::AMDIL, disasm, target, Some(compiler_id))?;
+        }
+    }
 
     Ok(())
 }
 
-enum YkFileKind {
-    FXO(PathBuf),
-    VSO(PathBuf),
-    PSO(PathBuf),
-}
-
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
-    let dll = unsafe { Atidxx64::try_load_lib_from(args.dll_path).expect("no library found") };
+    let dll = unsafe { Atidxx64::try_load_lib_from(&args.dll_path).expect("no library found") };
+    let mut compiler = Compiler::new(dll, &args.dll_path).expect("couldn't fingerprint library");
+    if let Some(cache_dir) = &args.cache_dir {
+        compiler = compiler.with_cache_dir(cache_dir).expect("couldn't create cache directory");
+    }
 
     let mut db = ShaderDb::from_file(args.db_path)?;
+    let compiler_id = db.insert_compiler_identity(compiler.identity())?;
+
+    // Containers are identified by their magic, so extensions don't matter
+    let shaders = read_shader_files(&args.fxo_dir, |_| true)?;
+    for failure in &shaders.failures {
+        eprintln!("warning: skipping {}: {}", failure.path, failure.error);
+    }
 
-    let shaders = std::fs::read_dir(args.fxo_dir)
-        .expect("couldn't open directory")
-        .filter_map(|file| {
-            match file {
-                Ok(file) => {
-                    let name = file.file_name();
-                    let name = name.to_str()?;
-                    if name.ends_with(".fxo") {
-                        Some(YkFileKind::FXO(file.path()))
-                    } else if name.ends_with(".vso") {
-                        Some(YkFileKind::VSO(file.path()))
-                    } else if name.ends_with(".pso") {
-                        Some(YkFileKind::PSO(file.path()))
-                    } else {
-                        None
-                    }
-                },
-                Err(_) => None,
-            }
-        });
-
-    for file in shaders {
-        match file {
-            YkFileKind::FXO(path) => read_fxo(&dll, &args.shader_category, path, &mut db)?,
-            YkFileKind::VSO(path) => read_vso(&dll, &args.shader_category, path, &mut db)?,
-            YkFileKind::PSO(path) => read_pso(&dll, &args.shader_category, path, &mut db)?,
+    for file in shaders.files {
+        if YkContainerKind::detect(&file.data).is_some() {
+            read_container(&compiler, compiler_id, &args.shader_category, file, &args.targets, &mut db)?;
         }
     }
 
     Ok(())
 
-}
+}
\ No newline at end of file
@@ -0,0 +1,62 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use yk_fxo_disasm::db::ShaderDb;
+
+/// Merge other ShaderDbs into a single database
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// The database to merge into
+    #[clap(value_parser)]
+    db_path: PathBuf,
+
+    /// The databases to merge from
+    #[clap(value_parser, required = true)]
+    other_db_paths: Vec<PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    let mut db = ShaderDb::from_file(&args.db_path)?;
+
+    let mut total_conflicts = 0;
+    for other_db_path in args.other_db_paths {
+        let report = db.merge_from(&other_db_path)?;
+
+        println!("{}", other_db_path.display());
+        println!(
+            "\tShaderBytes: {} imported, {} duplicates skipped",
+            report.bytes_imported, report.bytes_duplicates
+        );
+        println!(
+            "\tShaderDisasm: {} imported, {} duplicates skipped",
+            report.disasm_imported, report.disasm_duplicates
+        );
+        if !report.conflicts.is_empty() {
+            println!("\tConflicts ({}):", report.conflicts.len());
+            for conflict in &report.conflicts {
+                let target = conflict
+                    .asic_target
+                    .map(|target| format!(", {}", target.to_str()))
+                    .unwrap_or_default();
+                println!(
+                    "\t\t{} {}/{} ({}, {}{target})",
+                    conflict.table,
+                    conflict.category,
+                    conflict.shader_name,
+                    conflict.shader_stage.to_str(),
+                    conflict.kind
+                );
+            }
+        }
+        total_conflicts += report.conflicts.len();
+    }
+
+    if total_conflicts > 0 {
+        println!("\n{total_conflicts} conflicting rows were not imported");
+    }
+
+    Ok(())
+}
@@ -1,42 +1,499 @@
 use amd_dx_gsa::{
-    amd_isa_devices::FIRST_RDNA2_ASIC, dxbc::get_shader_bytecode, Atidxx64, ShaderCompileError,
+    amd_isa_devices::{
+        AmdAsic, FIRST_GCN_ASIC, FIRST_RDNA2_ASIC, FIRST_RDNA3_ASIC, FIRST_RDNA_ASIC,
+    },
+    dxbc::get_shader_bytecode,
+    Atidxx64, ShaderCompileError,
+};
+use object::{Object, ObjectSection, ObjectSymbol, SectionKind};
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
 };
-use object::{Object, ObjectSection};
 
-pub fn compile_dxbc_to_rdna2<'a, T, F: FnOnce(&[u8]) -> T>(
-    dll: &Atidxx64,
-    dxbc: &'a [u8],
-    callback: F,
-) -> Result<T, ShaderCompileError> {
-    let (_, bytecode) = get_shader_bytecode(dxbc).expect("couldn't extract bytecode from DXBC");
-    dll.inspect_compiled_shader(
-        FIRST_RDNA2_ASIC,
-        amd_dx_gsa::AmdDxGsaShaderSource::DxAsmBinary(bytecode),
-        vec![],
-        |elf| {
-            let obj_file = object::File::parse(elf).expect("invalid ELF produced by atidxx64.dll");
-            let section = obj_file.section_by_name(".text").expect("no text section");
-            callback(section.data().expect(".text has no data"))
-        },
-    )
-}
-
-pub fn compile_dxbc_to_amdil_text<'dll: 'dxbc, 'dxbc: 'amdil, 'amdil, T: 'dll, F: FnOnce(&'amdil [u8]) -> T>(
-    dll: &'dll Atidxx64,
-    dxbc: &'dxbc [u8],
+use crate::{
+    db::{sha256, AsicTarget},
+    dxbc::parse_dxbc_chunks,
+    worker::Supervisor,
+};
+
+/// The ways compiling a shader with atidxx64.dll and reading its output can fail
+#[derive(Debug)]
+pub enum CompileError {
+    /// The shader bytecode couldn't be extracted from the DXBC container
+    BytecodeExtraction {
+        /// A description of the DXBC header, see [describe_dxbc_header]
+        header: String,
+    },
+    /// atidxx64.dll failed to compile the shader
+    Gsa(ShaderCompileError),
+    /// atidxx64.dll produced something which isn't a valid ELF
+    InvalidElf(object::Error),
+    /// The ELF doesn't have a section we need
+    MissingSection {
+        name: &'static str,
+        /// The names of the sections the ELF does have
+        sections: Vec<String>,
+    },
+    /// The ELF has a section we need, but it's empty
+    EmptySection { name: &'static str },
+    /// A worker process reported that compilation failed, see [crate::worker]
+    WorkerFailed { message: String },
+    /// The worker process crashed while compiling the shader
+    WorkerCrashed { status: String },
+    /// The worker process didn't finish compiling the shader in time, and was killed
+    WorkerTimedOut { timeout: Duration },
+    /// The worker process couldn't be started
+    WorkerIo(std::io::Error),
+}
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompileError::BytecodeExtraction { header } => {
+                write!(f, "couldn't extract bytecode from DXBC ({header})")
+            }
+            CompileError::Gsa(e) => write!(f, "atidxx64.dll failed to compile shader: {e}"),
+            CompileError::InvalidElf(e) => write!(f, "invalid ELF produced by atidxx64.dll: {e}"),
+            CompileError::MissingSection { name, sections } => write!(
+                f,
+                "no {name} section in compiled ELF (sections: {})",
+                sections.join(", ")
+            ),
+            CompileError::EmptySection { name } => write!(f, "{name} section in compiled ELF is empty"),
+            CompileError::WorkerFailed { message } => write!(f, "{message}"),
+            CompileError::WorkerCrashed { status } => write!(f, "compiler crashed ({status})"),
+            CompileError::WorkerTimedOut { timeout } => {
+                write!(f, "compiler timed out after {}s", timeout.as_secs_f32())
+            }
+            CompileError::WorkerIo(e) => write!(f, "couldn't start compiler worker: {e}"),
+        }
+    }
+}
+impl std::error::Error for CompileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CompileError::Gsa(e) => Some(e),
+            CompileError::InvalidElf(e) => Some(e),
+            CompileError::WorkerIo(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl From<ShaderCompileError> for CompileError {
+    fn from(value: ShaderCompileError) -> Self {
+        CompileError::Gsa(value)
+    }
+}
+impl From<object::Error> for CompileError {
+    fn from(value: object::Error) -> Self {
+        CompileError::InvalidElf(value)
+    }
+}
+
+/// Describes the magic and chunks of a DXBC container, for error messages
+fn describe_dxbc_header(dxbc: &[u8]) -> String {
+    let magic = String::from_utf8_lossy(dxbc.get(0..4).unwrap_or(dxbc)).into_owned();
+    match parse_dxbc_chunks(dxbc) {
+        Ok((_, chunks)) => {
+            let fourccs: Vec<_> = chunks
+                .iter()
+                .map(|chunk| String::from_utf8_lossy(&chunk.fourcc).into_owned())
+                .collect();
+            format!("magic {magic:?}, chunks [{}]", fourccs.join(", "))
+        }
+        Err(_) => format!("magic {magic:?}, unreadable chunk table"),
+    }
+}
+
+/// The ASIC atidxx64.dll is asked to compile for when targeting the given architecture
+fn target_asic(target: AsicTarget) -> AmdAsic {
+    match target {
+        AsicTarget::GCN => FIRST_GCN_ASIC,
+        AsicTarget::RDNA1 => FIRST_RDNA_ASIC,
+        AsicTarget::RDNA2 => FIRST_RDNA2_ASIC,
+        AsicTarget::RDNA3 => FIRST_RDNA3_ASIC,
+    }
+}
+
+/// A section of the ELF produced by atidxx64.dll
+#[derive(Debug, Clone)]
+pub struct CompiledSection {
+    pub name: String,
+    pub kind: SectionKind,
+    pub address: u64,
+    pub data: Vec<u8>,
+}
+
+/// A symbol of the ELF produced by atidxx64.dll
+#[derive(Debug, Clone)]
+pub struct CompiledSymbol {
+    pub name: String,
+    pub address: u64,
+    pub size: u64,
+    /// The name of the section the symbol is defined in, if any
+    pub section: Option<String>,
+}
+
+/// The ELF produced by compiling a shader with atidxx64.dll, and its contents.
+///
+/// The ELF contains the machine code (`.text`), the AMDIL disassembly (`.amdil_disassembly`),
+/// and various notes and metadata sections describing register and resource usage.
+#[derive(Debug, Clone)]
+pub struct CompiledShader {
+    pub elf: Vec<u8>,
+    pub sections: Vec<CompiledSection>,
+    pub symbols: Vec<CompiledSymbol>,
+}
+impl CompiledShader {
+    pub fn from_elf(elf: Vec<u8>) -> Result<Self, CompileError> {
+        let obj_file = object::File::parse(elf.as_slice())?;
+
+        let sections = obj_file
+            .sections()
+            .map(|section| CompiledSection {
+                name: section.name().unwrap_or_default().to_owned(),
+                kind: section.kind(),
+                address: section.address(),
+                data: section.data().unwrap_or_default().to_vec(),
+            })
+            .collect();
+
+        let symbols = obj_file
+            .symbols()
+            .map(|symbol| CompiledSymbol {
+                name: symbol.name().unwrap_or_default().to_owned(),
+                address: symbol.address(),
+                size: symbol.size(),
+                section: symbol
+                    .section_index()
+                    .and_then(|idx| obj_file.section_by_index(idx).ok())
+                    .and_then(|section| section.name().ok().map(str::to_owned)),
+            })
+            .collect();
+
+        Ok(Self {
+            elf,
+            sections,
+            symbols,
+        })
+    }
+
+    /// Returns the first section with the given name
+    pub fn section(&self, name: &str) -> Option<&CompiledSection> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    /// Returns the data of a section which must be present and non-empty
+    fn required_section_data(&self, name: &'static str) -> Result<&[u8], CompileError> {
+        let section = self.section(name).ok_or_else(|| CompileError::MissingSection {
+            name,
+            sections: self.sections.iter().map(|section| section.name.clone()).collect(),
+        })?;
+        if section.data.is_empty() {
+            return Err(CompileError::EmptySection { name });
+        }
+        Ok(&section.data)
+    }
+
+    /// Returns every note section, which hold metadata about the compiled program
+    pub fn notes(&self) -> impl Iterator<Item = &CompiledSection> {
+        self.sections
+            .iter()
+            .filter(|section| section.kind == SectionKind::Note)
+    }
+
+    /// The machine code for the target architecture
+    pub fn text(&self) -> Result<&[u8], CompileError> {
+        self.required_section_data(".text")
+    }
+
+    /// The AMDIL disassembly of the shader, as text
+    pub fn amdil_disassembly(&self) -> Result<&[u8], CompileError> {
+        self.required_section_data(".amdil_disassembly")
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The number of compilations served from and missing from the cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Identifies a specific build of atidxx64.dll, as different driver versions produce different output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompilerIdentity {
+    /// The SHA-256 of the DLL file
+    pub sha256: Vec<u8>,
+    /// The file version from the DLL's version resource (e.g. "31.0.21001.45002"), if it has one
+    pub file_version: Option<String>,
+    /// The path the DLL was loaded from
+    pub path: String,
+}
+impl CompilerIdentity {
+    /// Reads and hashes the DLL at `path`
+    pub fn from_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let dll = std::fs::read(path)?;
+        Ok(Self {
+            sha256: sha256(&dll),
+            file_version: pe_file_version(&dll),
+            path: path.display().to_string(),
+        })
+    }
+
+    /// The file version if known, otherwise the start of the SHA-256
+    pub fn short_name(&self) -> String {
+        match &self.file_version {
+            Some(file_version) => file_version.clone(),
+            None => format!("sha256:{}", &to_hex(&self.sha256)[..16]),
+        }
+    }
+
+    /// Checks if `query` names this compiler: either its file version, or the start of its SHA-256 in hex
+    /// (with or without the `sha256:` prefix used by [CompilerIdentity::short_name])
+    pub fn matches(&self, query: &str) -> bool {
+        let sha256 = query.strip_prefix("sha256:").unwrap_or(query).to_ascii_lowercase();
+        self.file_version.as_deref() == Some(query) || (!sha256.is_empty() && to_hex(&self.sha256).starts_with(&sha256))
+    }
+}
+
+/// Finds the `VS_FIXEDFILEINFO` in a PE file's version resource and formats its file version.
+///
+/// Rather than walking the resource directory, this looks for the `VS_VERSION_INFO` key (UTF-16)
+/// and takes the first `VS_FIXEDFILEINFO` signature (0xFEEF04BD) after it.
+fn pe_file_version(pe: &[u8]) -> Option<String> {
+    const KEY: &str = "VS_VERSION_INFO";
+    const SIGNATURE: [u8; 4] = 0xFEEF04BD_u32.to_le_bytes();
+
+    let key: Vec<u8> = KEY.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let key_end = pe.windows(key.len()).position(|window| window == key)? + key.len();
+    // The key is followed by a null terminator and padding to a 4-byte boundary, so the signature is close by
+    let search_end = (key_end + 16).min(pe.len());
+    let signature_offset = pe[key_end..search_end]
+        .windows(4)
+        .position(|window| window == SIGNATURE)?;
+    let fixed_info = &pe[key_end + signature_offset..];
+
+    // dwSignature, dwStrucVersion, dwFileVersionMS, dwFileVersionLS
+    let read_u32 = |offset: usize| Some(u32::from_le_bytes(fixed_info.get(offset..offset + 4)?.try_into().unwrap()));
+    let (ms, ls) = (read_u32(8)?, read_u32(12)?);
+    Some(format!("{}.{}.{}.{}", ms >> 16, ms & 0xFFFF, ls >> 16, ls & 0xFFFF))
+}
+
+/// A directory of compiled ELFs, stored as `<key>.elf`.
+///
+/// The key is the SHA-256 of the DXBC, the target and the SHA-256 of the DLL which compiled it,
+/// so a different compiler version never returns stale output.
+struct CompileCache {
+    dir: PathBuf,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+impl CompileCache {
+    fn path(&self, key: &[u8]) -> PathBuf {
+        self.dir.join(format!("{}.elf", to_hex(key)))
+    }
+
+    /// Returns the cached output for `key`.
+    /// Entries which can't be parsed (e.g. truncated by a full disk) are deleted and treated as misses, so they get recompiled.
+    fn get(&self, key: &[u8]) -> Option<CompiledShader> {
+        let path = self.path(key);
+        let compiled = std::fs::read(&path).ok().and_then(|elf| match CompiledShader::from_elf(elf) {
+            Ok(compiled) => Some(compiled),
+            Err(e) => {
+                eprintln!("warning: discarding corrupt compile cache entry {}: {e}", path.display());
+                let _ = std::fs::remove_file(&path);
+                None
+            }
+        });
+        let counter = if compiled.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        compiled
+    }
+
+    fn put(&self, key: &[u8], elf: &[u8]) {
+        // Write to a temporary file first so a crash or a concurrent run never leaves a truncated entry
+        let path = self.path(key);
+        let tmp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+        let res = std::fs::write(&tmp_path, elf).and_then(|_| std::fs::rename(&tmp_path, &path));
+        if let Err(e) = res {
+            eprintln!("warning: couldn't write compile cache entry {}: {e}", path.display());
+        }
+    }
+}
+
+/// Finds the shader bytecode inside a DXBC container
+fn extract_bytecode(dxbc: &[u8]) -> Result<&[u8], CompileError> {
+    let (_, bytecode) = get_shader_bytecode(dxbc).map_err(|_| CompileError::BytecodeExtraction {
+        header: describe_dxbc_header(dxbc),
+    })?;
+    Ok(bytecode)
+}
+
+/// Something which can compile DXBC to an ELF
+pub trait CompileBackend {
+    fn compile_elf(&self, dxbc: &[u8], target: AsicTarget) -> Result<Vec<u8>, CompileError>;
+}
+impl CompileBackend for Atidxx64 {
+    fn compile_elf(&self, dxbc: &[u8], target: AsicTarget) -> Result<Vec<u8>, CompileError> {
+        let bytecode = extract_bytecode(dxbc)?;
+        Ok(self.inspect_compiled_shader(
+            target_asic(target),
+            amd_dx_gsa::AmdDxGsaShaderSource::DxAsmBinary(bytecode),
+            vec![],
+            |elf| elf.to_vec(),
+        )?)
+    }
+}
+
+/// Where a [Compiler] runs the DLL
+enum Backend {
+    /// In this process
+    Direct(Atidxx64),
+    /// In a worker process, see [crate::worker]
+    Isolated(Supervisor),
+}
+
+/// atidxx64.dll, with an optional on-disk cache of its output
+pub struct Compiler {
+    backend: Backend,
+    identity: CompilerIdentity,
+    cache: Option<CompileCache>,
+}
+impl Compiler {
+    /// Wraps a loaded DLL, reading the file at `dll_path` to identify it
+    pub fn new(dll: Atidxx64, dll_path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self {
+            backend: Backend::Direct(dll),
+            identity: CompilerIdentity::from_file(dll_path)?,
+            cache: None,
+        })
+    }
+
+    /// Compiles in worker processes managed by `supervisor`, which must load the DLL at `dll_path`.
+    /// The DLL isn't loaded into this process.
+    pub fn isolated(supervisor: Supervisor, dll_path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self {
+            backend: Backend::Isolated(supervisor),
+            identity: CompilerIdentity::from_file(dll_path)?,
+            cache: None,
+        })
+    }
+
+    /// Stores compiler output in `dir`, reusing it whenever the same DXBC is compiled for the same target by the same DLL
+    pub fn with_cache_dir(mut self, dir: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        self.cache = Some(CompileCache {
+            dir,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        });
+        Ok(self)
+    }
+
+    /// Identifies the DLL, so output from different driver versions can be told apart
+    pub fn identity(&self) -> &CompilerIdentity {
+        &self.identity
+    }
+
+    /// Returns the cache statistics, or None if there is no cache
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|cache| CacheStats {
+            hits: cache.hits.load(Ordering::Relaxed),
+            misses: cache.misses.load(Ordering::Relaxed),
+        })
+    }
+
+    fn cache_key(&self, dxbc: &[u8], target: AsicTarget) -> Vec<u8> {
+        let mut key_data = dxbc.to_vec();
+        key_data.extend_from_slice(target.to_str().as_bytes());
+        key_data.extend_from_slice(&self.identity.sha256);
+        sha256(&key_data)
+    }
+
+    fn compile_uncached(&self, dxbc: &[u8], target: AsicTarget) -> Result<Vec<u8>, CompileError> {
+        match &self.backend {
+            Backend::Direct(dll) => dll.compile_elf(dxbc, target),
+            // Check the bytecode can be extracted before sending it off,
+            // so extraction failures keep their own error instead of coming back as a worker message
+            Backend::Isolated(supervisor) => {
+                extract_bytecode(dxbc)?;
+                supervisor.compile_elf(dxbc, target)
+            }
+        }
+    }
+}
+
+/// Compiles a DXBC shader for the given architecture, returning the full ELF output of the compiler.
+///
+/// If the compiler has a cache, it is consulted first and successful compilations are added to it.
+pub fn compile_dxbc(compiler: &Compiler, dxbc: &[u8], target: AsicTarget) -> Result<CompiledShader, CompileError> {
+    let Some(cache) = &compiler.cache else {
+        return CompiledShader::from_elf(compiler.compile_uncached(dxbc, target)?);
+    };
+
+    let key = compiler.cache_key(dxbc, target);
+    if let Some(compiled) = cache.get(&key) {
+        return Ok(compiled);
+    }
+    let compiled = CompiledShader::from_elf(compiler.compile_uncached(dxbc, target)?)?;
+    cache.put(&key, &compiled.elf);
+    Ok(compiled)
+}
+
+pub fn compile_dxbc_to_amdil_text<T, F: FnOnce(&[u8]) -> T>(
+    compiler: &Compiler,
+    dxbc: &[u8],
+    target: AsicTarget,
     callback: F,
-) -> Result<T, ShaderCompileError> {
-    let (_, bytecode) = get_shader_bytecode(dxbc).expect("couldn't extract bytecode from DXBC");
-    dll.inspect_compiled_shader(
-        FIRST_RDNA2_ASIC,
-        amd_dx_gsa::AmdDxGsaShaderSource::DxAsmBinary(bytecode),
-        vec![],
-        |elf| {
-            let obj_file = object::File::parse(elf).expect("invalid ELF produced by atidxx64.dll");
-            let section = obj_file
-                .section_by_name(".amdil_disassembly")
-                .expect("no amdil_disassembly section");
-            callback(section.data().expect(".amdil_disassembly has no data"))
-        },
-    )
+) -> Result<T, CompileError> {
+    let compiled = compile_dxbc(compiler, dxbc, target)?;
+    Ok(callback(compiled.amdil_disassembly()?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compiler_identity_matches() {
+        let compiler = CompilerIdentity {
+            sha256: vec![0xab, 0xcd, 0xef],
+            file_version: Some("31.0.21001.45002".to_owned()),
+            path: "atidxx64.dll".to_owned(),
+        };
+        assert!(compiler.matches("31.0.21001.45002"));
+        assert!(compiler.matches("ABCD"));
+        assert!(compiler.matches("sha256:abcdef"));
+        assert!(!compiler.matches("31.0"));
+        assert!(!compiler.matches("abce"));
+        assert!(!compiler.matches(""));
+    }
+
+    #[test]
+    fn corrupt_cache_entries_are_misses() {
+        let dir = std::env::temp_dir().join(format!("yk_fxo_disasm_cache_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cache = CompileCache {
+            dir: dir.clone(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        };
+        let key = sha256(b"truncated");
+        std::fs::write(cache.path(&key), b"\x7fELF\x02").unwrap();
+
+        assert!(cache.get(&key).is_none());
+        assert!(!cache.path(&key).exists());
+        assert_eq!(cache.hits.load(Ordering::Relaxed), 0);
+        assert_eq!(cache.misses.load(Ordering::Relaxed), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
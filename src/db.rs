@@ -5,15 +5,91 @@
 //! 
 //! ## ShaderBytes
 //! `ShaderBytes` holds the raw bytes for each shader, wihtout yakuza-specific wrappings. This is intended to be used when matching up shader names against 
+//! 
+//! # Version 2
+//! Version 2 adds the `DbInfo` table.
+//! 
+//! ## DbInfo
+//! `DbInfo` is a Key/Value table of metadata about the database.
+//! `ReadCompatVersion` holds the oldest database version a tool must support to query (but not modify) this database.
+//! Migrations which only add tables or columns should leave it alone, so that older tools can still use [ShaderDb::open_read_only] on newer databases.
+//! 
+//! # Version 3
+//! Version 3 adds the `ContainerMismatch` column to `ShaderBytes`,
+//! which is set when the container a shader came from disagrees with its DXBC about the stage or uses an unexpected shader model.
+//! 
+//! # Version 4
+//! Version 4 adds the `AsicTarget` column to `ShaderBytes` and `ShaderDisasm`, recording which GPU architecture compiler output was generated for.
+//! It is NULL for `ShaderBytes` rows which aren't compiler output (e.g. DXBC), and defaults to RDNA2 for `ShaderDisasm` rows from older versions.
+//! 
+//! # Version 5
+//! Version 5 adds the `CompilerIdentity` table, and the `CompilerId` column to `ShaderDisasm` which references it.
+//! `CompilerId` is NULL for rows from older versions, where the compiler wasn't recorded.
+//! 
+//! ## CompilerIdentity
+//! `CompilerIdentity` has a row for each `atidxx64.dll` which has produced disassembly, see [CompilerIdentity].
+//! DLLs are identified by the SHA-256 of the file, the path and version are informational.
+//! 
+//! # Version 6
+//! Version 6 doesn't change the schema, but `ShaderBytes` rows may have the `ELF` BytesType, which older tools can't read.
+//! `ReadCompatVersion` is raised to 6 so those tools refuse the database instead of failing on the first ELF row.
+//! 
+//! # Version 7
+//! Version 7 adds the `CompilerId` column to `ShaderBytes`, recording which DLL produced `ELF` rows like `ShaderDisasm.CompilerId`.
+//! It is NULL for rows which aren't compiler output, and for ELF rows from version 6.
+
+use std::{path::Path, time::Duration};
+
+use rusqlite::{
+    backup::Backup,
+    types::{FromSql, FromSqlError, FromSqlResult, ValueRef},
+    Connection, DatabaseName, OpenFlags, OptionalExtension, Row, Transaction,
+};
 
-use std::path::Path;
+use crate::compile::CompilerIdentity;
 
-use rusqlite::{Connection, Transaction};
+#[derive(Debug)]
+pub enum DbError {
+    Sqlite(rusqlite::Error),
+    /// The database was created by a newer version of this tool, so can't be modified by this version.
+    /// It may still be possible to query it with [ShaderDb::open_read_only].
+    NewerVersion { db_version: u32 },
+    /// The database was created by a newer version of this tool with a schema this version can't read.
+    IncompatibleVersion { db_version: u32, read_compat_version: u32 },
+}
+impl std::fmt::Display for DbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DbError::Sqlite(e) => write!(f, "SQLite error: {e}"),
+            DbError::NewerVersion { db_version } => write!(
+                f,
+                "database version {db_version} is newer than the supported version {CURR_DB_VERSION}. Please update this tool to the latest version, or open the database read-only."
+            ),
+            DbError::IncompatibleVersion { db_version, read_compat_version } => write!(
+                f,
+                "database version {db_version} can only be read by tools supporting version {read_compat_version} or newer, but this tool supports version {CURR_DB_VERSION}. Please update this tool to the latest version."
+            ),
+        }
+    }
+}
+impl std::error::Error for DbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DbError::Sqlite(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl From<rusqlite::Error> for DbError {
+    fn from(value: rusqlite::Error) -> Self {
+        DbError::Sqlite(value)
+    }
+}
 
-pub type DbResult<T> = rusqlite::Result<T>;
+pub type DbResult<T> = Result<T, DbError>;
 
 /// The version of the database the code expects to work with.
-pub const CURR_DB_VERSION: u32 = 1;
+pub const CURR_DB_VERSION: u32 = 7;
 
 pub struct ShaderDb {
     conn: Connection,
@@ -24,17 +100,37 @@ pub struct ShaderDb {
 pub enum ShaderStage {
     Vertex,
     Fragment,
+    Geometry,
+    Hull,
+    Domain,
+    Compute,
 }
 impl ShaderStage {
     pub fn to_str(self) -> &'static str {
         self.into()
     }
+
+    /// The conventional D3D abbreviation for the stage, e.g. "vs" for [ShaderStage::Vertex]
+    pub fn short_name(self) -> &'static str {
+        match self {
+            ShaderStage::Vertex => "vs",
+            ShaderStage::Fragment => "ps",
+            ShaderStage::Geometry => "gs",
+            ShaderStage::Hull => "hs",
+            ShaderStage::Domain => "ds",
+            ShaderStage::Compute => "cs",
+        }
+    }
 }
 impl From<ShaderStage> for &'static str {
     fn from(value: ShaderStage) -> Self {
         match value {
             ShaderStage::Vertex => "Vertex",
             ShaderStage::Fragment => "Fragment",
+            ShaderStage::Geometry => "Geometry",
+            ShaderStage::Hull => "Hull",
+            ShaderStage::Domain => "Domain",
+            ShaderStage::Compute => "Compute",
         }
     }
 }
@@ -45,14 +141,25 @@ impl TryFrom<&str> for ShaderStage {
         match value {
             "Vertex" => Ok(ShaderStage::Vertex),
             "Fragment" => Ok(ShaderStage::Fragment),
+            "Geometry" => Ok(ShaderStage::Geometry),
+            "Hull" => Ok(ShaderStage::Hull),
+            "Domain" => Ok(ShaderStage::Domain),
+            "Compute" => Ok(ShaderStage::Compute),
             _ => Err(format!("Invalid ShaderStage '{value}'"))
         }
     }
 }
+impl FromSql for ShaderStage {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        ShaderStage::try_from(value.as_str()?).map_err(|e| FromSqlError::Other(e.into()))
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum BytesType {
     DXBC,
+    /// The full ELF output of atidxx64.dll, see [crate::compile::CompiledShader]
+    ELF,
 }
 impl BytesType {
     pub fn to_str(self) -> &'static str {
@@ -63,6 +170,7 @@ impl From<BytesType> for &'static str {
     fn from(value: BytesType) -> Self {
         match value {
             BytesType::DXBC => "DXBC",
+            BytesType::ELF => "ELF",
         }
     }
 }
@@ -72,10 +180,16 @@ impl TryFrom<&str> for BytesType {
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         match value {
             "DXBC" => Ok(BytesType::DXBC),
+            "ELF" => Ok(BytesType::ELF),
             _ => Err(format!("Invalid BytesType '{value}'"))
         }
     }
 }
+impl FromSql for BytesType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        BytesType::try_from(value.as_str()?).map_err(|e| FromSqlError::Other(e.into()))
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum DisasmType {
@@ -103,13 +217,145 @@ impl TryFrom<&str> for DisasmType {
         }
     }
 }
+impl FromSql for DisasmType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        DisasmType::try_from(value.as_str()?).map_err(|e| FromSqlError::Other(e.into()))
+    }
+}
+
+/// The AMD GPU architectures shaders can be compiled for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, clap::ValueEnum)]
+pub enum AsicTarget {
+    GCN,
+    RDNA1,
+    RDNA2,
+    RDNA3,
+}
+impl AsicTarget {
+    pub fn to_str(self) -> &'static str {
+        self.into()
+    }
+}
+impl From<AsicTarget> for &'static str {
+    fn from(value: AsicTarget) -> Self {
+        match value {
+            AsicTarget::GCN => "GCN",
+            AsicTarget::RDNA1 => "RDNA1",
+            AsicTarget::RDNA2 => "RDNA2",
+            AsicTarget::RDNA3 => "RDNA3",
+        }
+    }
+}
+impl TryFrom<&str> for AsicTarget {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "GCN" => Ok(AsicTarget::GCN),
+            "RDNA1" => Ok(AsicTarget::RDNA1),
+            "RDNA2" => Ok(AsicTarget::RDNA2),
+            "RDNA3" => Ok(AsicTarget::RDNA3),
+            _ => Err(format!("Invalid AsicTarget '{value}'"))
+        }
+    }
+}
+impl FromSql for AsicTarget {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        AsicTarget::try_from(value.as_str()?).map_err(|e| FromSqlError::Other(e.into()))
+    }
+}
+
+/// Selects a subset of the shaders stored in a [ShaderDb].
+///
+/// `category` and `name` are SQLite `GLOB` patterns (e.g. `"chara_*"`), and `None` matches everything.
+#[derive(Debug, Clone, Default)]
+pub struct ShaderFilter {
+    pub category: Option<String>,
+    pub name: Option<String>,
+    pub stage: Option<ShaderStage>,
+}
+
+/// A row of the `ShaderBytes` table
+#[derive(Debug, Clone)]
+pub struct ShaderBytesRow {
+    pub category: String,
+    pub shader_name: String,
+    pub shader_stage: ShaderStage,
+    pub bytes_type: BytesType,
+    pub bytes: Vec<u8>,
+    pub sha256: Vec<u8>,
+    /// Set if the container disagreed with the shader bytes, see [crate::yk::ContainerWarning]
+    pub container_mismatch: bool,
+    /// The architecture the bytes were compiled for, if they are compiler output
+    pub asic_target: Option<AsicTarget>,
+    /// The DLL which compiled the bytes, if they are compiler output and it was recorded
+    pub compiler: Option<CompilerIdentity>,
+}
+
+/// A row of the `ShaderDisasm` table
+#[derive(Debug, Clone)]
+pub struct ShaderDisasmRow {
+    pub category: String,
+    pub shader_name: String,
+    pub shader_stage: ShaderStage,
+    pub disasm_type: DisasmType,
+    pub disasm: String,
+    pub asic_target: AsicTarget,
+    /// The DLL which produced the disassembly, if it was recorded
+    pub compiler: Option<CompilerIdentity>,
+}
+
+/// A shader which exists in both databases of a [ShaderDb::merge_from] with different contents
+#[derive(Debug, Clone)]
+pub struct MergeConflict {
+    /// The table the conflicting rows are in, i.e. "ShaderBytes" or "ShaderDisasm"
+    pub table: &'static str,
+    pub category: String,
+    pub shader_name: String,
+    pub shader_stage: ShaderStage,
+    /// The BytesType or DisasmType of the conflicting rows
+    pub kind: String,
+    pub asic_target: Option<AsicTarget>,
+}
+
+/// The outcome of a [ShaderDb::merge_from]
+#[derive(Debug, Clone, Default)]
+pub struct MergeReport {
+    pub bytes_imported: usize,
+    pub bytes_duplicates: usize,
+    pub disasm_imported: usize,
+    pub disasm_duplicates: usize,
+    /// Rows which were not imported because the same key maps to different contents in each database
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/// Reads a [CompilerIdentity] from the SHA256, FileVersion and Path columns starting at `first_column`,
+/// which are all NULL if the compiler wasn't recorded
+fn compiler_identity_from_row(row: &Row, first_column: usize) -> rusqlite::Result<Option<CompilerIdentity>> {
+    let Some(sha256) = row.get(first_column)? else {
+        return Ok(None);
+    };
+    Ok(Some(CompilerIdentity {
+        sha256,
+        file_version: row.get(first_column + 1)?,
+        path: row.get(first_column + 2)?,
+    }))
+}
+
+/// Computes the digest stored in the `SHA256` column of `ShaderBytes` and `CompilerIdentity`
+pub fn sha256(bytes: &[u8]) -> Vec<u8> {
+    use ring::digest::{Context, SHA256};
+    let mut ctx = Context::new(&SHA256);
+    ctx.update(bytes);
+    ctx.finish().as_ref().to_vec()
+}
 
 impl ShaderDb {
     pub fn from_file<P: AsRef<Path>>(path: P) -> DbResult<Self> {
         let conn = Connection::open(path)?;
         let version = conn.query_row("SELECT user_version FROM pragma_user_version", [], |row| row.get(0))?;
         if version > CURR_DB_VERSION {
-            panic!("Database loaded with higher user_version than supported by this binary. Please update this tool to the latest version.");
+            return Err(DbError::NewerVersion { db_version: version });
         }
         let mut db = Self {
             conn, version
@@ -118,6 +364,61 @@ impl ShaderDb {
         Ok(db)
     }
 
+    /// Open an existing database for queries only. The file is never written to.
+    ///
+    /// Unlike [ShaderDb::from_file], this accepts databases created by newer versions of this tool
+    /// as long as their `ReadCompatVersion` says this version can still read them.
+    /// Older databases are copied into memory and the copy is migrated, see [ShaderDb::migrated_copy].
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> DbResult<Self> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+        let version = conn.query_row("SELECT user_version FROM pragma_user_version", [], |row| row.get(0))?;
+        if version < CURR_DB_VERSION {
+            return Self { conn, version }.migrated_copy();
+        }
+        if version > CURR_DB_VERSION {
+            let read_compat_version: Option<u32> = conn.query_row(
+                "SELECT Value FROM DbInfo WHERE Key = 'ReadCompatVersion'",
+                [],
+                |row| row.get(0)
+            ).optional()?;
+            // Every database version with a DbInfo table records ReadCompatVersion, so treat a missing one as incompatible.
+            let read_compat_version = read_compat_version.unwrap_or(version);
+            if read_compat_version > CURR_DB_VERSION {
+                return Err(DbError::IncompatibleVersion { db_version: version, read_compat_version });
+            }
+        }
+        Ok(Self {
+            conn, version
+        })
+    }
+
+    /// Copies the whole database into a new in-memory database and migrates the copy to [CURR_DB_VERSION],
+    /// so older databases can be read without modifying them
+    fn migrated_copy(&self) -> DbResult<Self> {
+        let mut conn = Connection::open_in_memory()?;
+        self.copy_into(&mut conn, DatabaseName::Main)?;
+        let mut copy = Self {
+            conn, version: self.version
+        };
+        copy.migrate()?;
+        Ok(copy)
+    }
+
+    /// Copies the whole database into the (empty) database `name` of `conn`, including the user_version
+    fn copy_into(&self, conn: &mut Connection, name: DatabaseName) -> DbResult<()> {
+        // In-memory destinations can't change their page size after the copy starts, so match it up front
+        let page_size: u32 = self.conn.query_row("SELECT page_size FROM pragma_page_size", [], |row| row.get(0))?;
+        conn.pragma_update(Some(name), "page_size", page_size)?;
+        Backup::new_with_names(&self.conn, DatabaseName::Main, conn, name)?
+            .run_to_completion(1024, Duration::ZERO, None)?;
+        Ok(())
+    }
+
+    /// The version of the database file, which may be higher than [CURR_DB_VERSION] if opened with [ShaderDb::open_read_only]
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
     /// Perform a migration and update the DB internal user_version all in one transaction
     fn push_version<F: FnOnce(&Transaction) -> DbResult<()>>(&mut self, f: F, new_version: u32) -> DbResult<()> {
         let tx = self.conn.transaction()?;
@@ -150,28 +451,339 @@ impl ShaderDb {
                 Ok(())
             }, 1)?;
         }
+        if self.version == 1 {
+            self.push_version(|tx| {
+                tx.execute("CREATE TABLE DbInfo (
+                    Key TEXT PRIMARY KEY NOT NULL,
+                    Value INTEGER NOT NULL
+                )", [])?;
+                tx.execute("INSERT INTO DbInfo (Key, Value) VALUES ('ReadCompatVersion', 1)", [])?;
+                Ok(())
+            }, 2)?;
+        }
+        if self.version == 2 {
+            self.push_version(|tx| {
+                tx.execute("ALTER TABLE ShaderBytes ADD COLUMN ContainerMismatch INTEGER NOT NULL DEFAULT 0", [])?;
+                Ok(())
+            }, 3)?;
+        }
+        if self.version == 3 {
+            self.push_version(|tx| {
+                tx.execute("ALTER TABLE ShaderBytes ADD COLUMN AsicTarget TEXT", [])?;
+                tx.execute("ALTER TABLE ShaderDisasm ADD COLUMN AsicTarget TEXT NOT NULL DEFAULT 'RDNA2'", [])?;
+                Ok(())
+            }, 4)?;
+        }
+        if self.version == 4 {
+            self.push_version(|tx| {
+                tx.execute("CREATE TABLE CompilerIdentity (
+                    Id INTEGER PRIMARY KEY,
+                    SHA256 BLOB NOT NULL UNIQUE,
+                    FileVersion TEXT,
+                    Path TEXT NOT NULL
+                )", [])?;
+                tx.execute("ALTER TABLE ShaderDisasm ADD COLUMN CompilerId INTEGER REFERENCES CompilerIdentity(Id)", [])?;
+                Ok(())
+            }, 5)?;
+        }
+        if self.version == 5 {
+            self.push_version(|tx| {
+                tx.execute("UPDATE DbInfo SET Value = 6 WHERE Key = 'ReadCompatVersion'", [])?;
+                Ok(())
+            }, 6)?;
+        }
+        if self.version == 6 {
+            self.push_version(|tx| {
+                tx.execute("ALTER TABLE ShaderBytes ADD COLUMN CompilerId INTEGER REFERENCES CompilerIdentity(Id)", [])?;
+                Ok(())
+            }, 7)?;
+        }
         // Insert further migrations here when necessary.
         assert_eq!(self.version, CURR_DB_VERSION);
         Ok(())
     }
 
-    pub fn insert_bytes(&mut self, category: &str, shader_name: &str, shader_stage: ShaderStage, bytes_type: BytesType, bytes: &[u8]) -> DbResult<()> {
-        use ring::digest::{Context, SHA256};
-        let mut ctx = Context::new(&SHA256);
-        ctx.update(bytes);
-        let digest = ctx.finish();
+    /// Records the bytes of a shader. `asic_target` and `compiler_id` (see [ShaderDb::insert_compiler_identity]) should be set for compiler output.
+    #[allow(clippy::too_many_arguments)]
+    pub fn insert_bytes(&mut self, category: &str, shader_name: &str, shader_stage: ShaderStage, bytes_type: BytesType, bytes: &[u8], container_mismatch: bool, asic_target: Option<AsicTarget>, compiler_id: Option<i64>) -> DbResult<()> {
+        let digest = sha256(bytes);
         self.conn.execute(
-            "INSERT INTO ShaderBytes (Category, ShaderName, ShaderStage, BytesType, Bytes, SHA256) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            (category, shader_name, shader_stage.to_str(), bytes_type.to_str(), bytes, digest.as_ref())
+            "INSERT INTO ShaderBytes (Category, ShaderName, ShaderStage, BytesType, Bytes, SHA256, ContainerMismatch, AsicTarget, CompilerId) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            (category, shader_name, shader_stage.to_str(), bytes_type.to_str(), bytes, digest, container_mismatch, asic_target.map(AsicTarget::to_str), compiler_id)
         )?;
         Ok(())
     }
 
-    pub fn insert_disasm(&mut self, category: &str, shader_name: &str, shader_stage: ShaderStage, disasm_type: DisasmType, disasm: &str) -> DbResult<()> {
+    /// Records a compiler, returning the `CompilerIdentity` Id to pass to [ShaderDb::insert_bytes] and [ShaderDb::insert_disasm].
+    /// If a compiler with the same SHA-256 is already recorded, its Id is returned and the existing row is kept.
+    pub fn insert_compiler_identity(&mut self, identity: &CompilerIdentity) -> DbResult<i64> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO CompilerIdentity (SHA256, FileVersion, Path) VALUES (?1, ?2, ?3)",
+            (&identity.sha256, &identity.file_version, &identity.path)
+        )?;
+        Ok(self.conn.query_row(
+            "SELECT Id FROM CompilerIdentity WHERE SHA256 = ?1",
+            [&identity.sha256],
+            |row| row.get(0)
+        )?)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn insert_disasm(&mut self, category: &str, shader_name: &str, shader_stage: ShaderStage, disasm_type: DisasmType, disasm: &str, asic_target: AsicTarget, compiler_id: Option<i64>) -> DbResult<()> {
         self.conn.execute(
-            "INSERT INTO ShaderDisasm (Category, ShaderName, ShaderStage, DisasmType, Disasm) VALUES (?1, ?2, ?3, ?4, ?5)",
-            (category, shader_name, shader_stage.to_str(), disasm_type.to_str(), disasm)
+            "INSERT INTO ShaderDisasm (Category, ShaderName, ShaderStage, DisasmType, Disasm, AsicTarget, CompilerId) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            (category, shader_name, shader_stage.to_str(), disasm_type.to_str(), disasm, asic_target.to_str(), compiler_id)
         )?;
         Ok(())
     }
+
+    /// Returns every `ShaderBytes` row matching the filter, ordered by category, name and stage.
+    pub fn query_bytes(&self, filter: &ShaderFilter) -> DbResult<Vec<ShaderBytesRow>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT Category, ShaderName, ShaderStage, BytesType, Bytes, ShaderBytes.SHA256, ContainerMismatch, AsicTarget, c.SHA256, c.FileVersion, c.Path FROM ShaderBytes
+            LEFT JOIN CompilerIdentity c ON c.Id = CompilerId
+            WHERE (?1 IS NULL OR Category GLOB ?1) AND (?2 IS NULL OR ShaderName GLOB ?2) AND (?3 IS NULL OR ShaderStage = ?3)
+            ORDER BY Category, ShaderName, ShaderStage, BytesType, AsicTarget"
+        )?;
+        let rows = stmt.query_map(
+            (&filter.category, &filter.name, filter.stage.map(ShaderStage::to_str)),
+            |row| Ok(ShaderBytesRow {
+                category: row.get(0)?,
+                shader_name: row.get(1)?,
+                shader_stage: row.get(2)?,
+                bytes_type: row.get(3)?,
+                bytes: row.get(4)?,
+                sha256: row.get(5)?,
+                container_mismatch: row.get(6)?,
+                asic_target: row.get(7)?,
+                compiler: compiler_identity_from_row(row, 8)?,
+            })
+        )?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Returns every `ShaderDisasm` row matching the filter, ordered by category, name and stage.
+    pub fn query_disasm(&self, filter: &ShaderFilter) -> DbResult<Vec<ShaderDisasmRow>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT Category, ShaderName, ShaderStage, DisasmType, Disasm, AsicTarget, c.SHA256, c.FileVersion, c.Path FROM ShaderDisasm
+            LEFT JOIN CompilerIdentity c ON c.Id = CompilerId
+            WHERE (?1 IS NULL OR Category GLOB ?1) AND (?2 IS NULL OR ShaderName GLOB ?2) AND (?3 IS NULL OR ShaderStage = ?3)
+            ORDER BY Category, ShaderName, ShaderStage, DisasmType, AsicTarget"
+        )?;
+        let rows = stmt.query_map(
+            (&filter.category, &filter.name, filter.stage.map(ShaderStage::to_str)),
+            |row| Ok(ShaderDisasmRow {
+                category: row.get(0)?,
+                shader_name: row.get(1)?,
+                shader_stage: row.get(2)?,
+                disasm_type: row.get(3)?,
+                disasm: row.get(4)?,
+                asic_target: row.get(5)?,
+                compiler: compiler_identity_from_row(row, 6)?,
+            })
+        )?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Imports all shaders from another existing database file into this one.
+    ///
+    /// The other database is opened with [ShaderDb::open_read_only] and copied into memory, so the file is never modified
+    /// and older databases are migrated in the copy only.
+    /// Rows which are already present with identical contents are skipped as duplicates,
+    /// and rows where the same (category, name, stage, type, target, compiler) key maps to different contents are reported as conflicts and not imported.
+    /// Compilers are matched by their SHA-256, for both ELF bytes and disassembly.
+    pub fn merge_from<P: AsRef<Path>>(&mut self, path: P) -> DbResult<MergeReport> {
+        let other = ShaderDb::open_read_only(&path)?;
+
+        self.conn.execute("ATTACH DATABASE ':memory:' AS other", [])?;
+        let report = other
+            .copy_into(&mut self.conn, DatabaseName::Attached("other"))
+            .and_then(|()| self.merge_attached());
+        self.conn.execute("DETACH DATABASE other", [])?;
+        report
+    }
+
+    /// Performs the merge for [ShaderDb::merge_from] once the other database is attached as `other`
+    fn merge_attached(&mut self) -> DbResult<MergeReport> {
+        let tx = self.conn.transaction()?;
+        let mut report = MergeReport::default();
+
+        // CompilerIds are local to each database, so compilers are compared by their SHA-256
+        const BYTES_SAME_KEY: &str = "m.Category = o.Category AND m.ShaderName = o.ShaderName AND m.ShaderStage = o.ShaderStage AND m.BytesType = o.BytesType AND m.AsicTarget IS o.AsicTarget
+            AND (SELECT SHA256 FROM main.CompilerIdentity WHERE Id = m.CompilerId) IS (SELECT SHA256 FROM other.CompilerIdentity WHERE Id = o.CompilerId)";
+        const DISASM_SAME_KEY: &str = "m.Category = o.Category AND m.ShaderName = o.ShaderName AND m.ShaderStage = o.ShaderStage AND m.DisasmType = o.DisasmType AND m.AsicTarget = o.AsicTarget
+            AND (SELECT SHA256 FROM main.CompilerIdentity WHERE Id = m.CompilerId) IS (SELECT SHA256 FROM other.CompilerIdentity WHERE Id = o.CompilerId)";
+
+        {
+            let mut stmt = tx.prepare(&format!(
+                "SELECT DISTINCT o.Category, o.ShaderName, o.ShaderStage, o.BytesType, o.AsicTarget FROM other.ShaderBytes o
+                WHERE EXISTS (SELECT 1 FROM main.ShaderBytes m WHERE {BYTES_SAME_KEY})
+                AND NOT EXISTS (SELECT 1 FROM main.ShaderBytes m WHERE {BYTES_SAME_KEY} AND m.SHA256 = o.SHA256)"
+            ))?;
+            let conflicts = stmt.query_map([], |row| Ok(MergeConflict {
+                table: "ShaderBytes",
+                category: row.get(0)?,
+                shader_name: row.get(1)?,
+                shader_stage: row.get(2)?,
+                kind: row.get(3)?,
+                asic_target: row.get(4)?,
+            }))?;
+            for conflict in conflicts {
+                report.conflicts.push(conflict?);
+            }
+
+            let mut stmt = tx.prepare(&format!(
+                "SELECT DISTINCT o.Category, o.ShaderName, o.ShaderStage, o.DisasmType, o.AsicTarget FROM other.ShaderDisasm o
+                WHERE EXISTS (SELECT 1 FROM main.ShaderDisasm m WHERE {DISASM_SAME_KEY})
+                AND NOT EXISTS (SELECT 1 FROM main.ShaderDisasm m WHERE {DISASM_SAME_KEY} AND m.Disasm = o.Disasm)"
+            ))?;
+            let conflicts = stmt.query_map([], |row| Ok(MergeConflict {
+                table: "ShaderDisasm",
+                category: row.get(0)?,
+                shader_name: row.get(1)?,
+                shader_stage: row.get(2)?,
+                kind: row.get(3)?,
+                asic_target: row.get(4)?,
+            }))?;
+            for conflict in conflicts {
+                report.conflicts.push(conflict?);
+            }
+        }
+
+        report.bytes_duplicates = tx.query_row(
+            &format!("SELECT COUNT(*) FROM other.ShaderBytes o WHERE EXISTS (SELECT 1 FROM main.ShaderBytes m WHERE {BYTES_SAME_KEY} AND m.SHA256 = o.SHA256)"),
+            [],
+            |row| row.get(0)
+        )?;
+        report.disasm_duplicates = tx.query_row(
+            &format!("SELECT COUNT(*) FROM other.ShaderDisasm o WHERE EXISTS (SELECT 1 FROM main.ShaderDisasm m WHERE {DISASM_SAME_KEY} AND m.Disasm = o.Disasm)"),
+            [],
+            |row| row.get(0)
+        )?;
+
+        tx.execute(
+            "INSERT OR IGNORE INTO main.CompilerIdentity (SHA256, FileVersion, Path)
+            SELECT SHA256, FileVersion, Path FROM other.CompilerIdentity",
+            []
+        )?;
+        report.bytes_imported = tx.execute(
+            &format!("INSERT INTO main.ShaderBytes (Category, ShaderName, ShaderStage, BytesType, Bytes, SHA256, ContainerMismatch, AsicTarget, CompilerId)
+            SELECT DISTINCT o.Category, o.ShaderName, o.ShaderStage, o.BytesType, o.Bytes, o.SHA256, o.ContainerMismatch, o.AsicTarget,
+                (SELECT mc.Id FROM main.CompilerIdentity mc JOIN other.CompilerIdentity oc ON mc.SHA256 = oc.SHA256 WHERE oc.Id = o.CompilerId)
+            FROM other.ShaderBytes o
+            WHERE NOT EXISTS (SELECT 1 FROM main.ShaderBytes m WHERE {BYTES_SAME_KEY})"),
+            []
+        )?;
+        report.disasm_imported = tx.execute(
+            &format!("INSERT INTO main.ShaderDisasm (Category, ShaderName, ShaderStage, DisasmType, Disasm, AsicTarget, CompilerId)
+            SELECT DISTINCT o.Category, o.ShaderName, o.ShaderStage, o.DisasmType, o.Disasm, o.AsicTarget,
+                (SELECT mc.Id FROM main.CompilerIdentity mc JOIN other.CompilerIdentity oc ON mc.SHA256 = oc.SHA256 WHERE oc.Id = o.CompilerId)
+            FROM other.ShaderDisasm o
+            WHERE NOT EXISTS (SELECT 1 FROM main.ShaderDisasm m WHERE {DISASM_SAME_KEY})"),
+            []
+        )?;
+
+        tx.commit()?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A database file in the temp directory, deleted when dropped
+    struct TempDb(std::path::PathBuf);
+    impl TempDb {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("yk_fxo_disasm_{name}_{}.db", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+    }
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// Creates a version 1 database with one shader, as written by the first version of gen_amdil_db
+    fn create_version_1(path: &Path) {
+        let conn = Connection::open(path).unwrap();
+        conn.execute_batch("
+            CREATE TABLE ShaderBytes (Category TEXT NOT NULL, ShaderName TEXT NOT NULL, ShaderStage TEXT NOT NULL, BytesType TEXT NOT NULL, Bytes BLOB NOT NULL, SHA256 BLOB NOT NULL);
+            CREATE TABLE ShaderDisasm (Category TEXT NOT NULL, ShaderName TEXT NOT NULL, ShaderStage TEXT NOT NULL, DisasmType TEXT NOT NULL, Disasm TEXT NOT NULL);
+            INSERT INTO ShaderBytes VALUES ('chara', 'skin', 'Vertex', 'DXBC', x'01020304', x'00');
+            INSERT INTO ShaderDisasm VALUES ('chara', 'skin', 'Vertex', 'AMDIL', 'il_vs_2_0');
+            PRAGMA user_version = 1;
+        ").unwrap();
+    }
+
+    fn file_version(path: &Path) -> u32 {
+        Connection::open(path).unwrap().query_row("SELECT user_version FROM pragma_user_version", [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn read_only_opens_old_databases_without_modifying_them() {
+        let file = TempDb::new("read_only_old");
+        create_version_1(&file.0);
+
+        let db = ShaderDb::open_read_only(&file.0).unwrap();
+        assert_eq!(db.version(), CURR_DB_VERSION);
+        let bytes = db.query_bytes(&ShaderFilter::default()).unwrap();
+        assert_eq!(bytes.len(), 1);
+        assert_eq!(bytes[0].bytes, [1, 2, 3, 4]);
+        assert_eq!(bytes[0].asic_target, None);
+        let disasm = db.query_disasm(&ShaderFilter::default()).unwrap();
+        assert_eq!(disasm.len(), 1);
+        assert_eq!(disasm[0].asic_target, AsicTarget::RDNA2);
+        assert!(disasm[0].compiler.is_none());
+        drop(db);
+
+        assert_eq!(file_version(&file.0), 1);
+    }
+
+    #[test]
+    fn merge_leaves_the_other_database_alone() {
+        let file = TempDb::new("merge_main");
+        let other = TempDb::new("merge_other");
+        create_version_1(&other.0);
+
+        let mut db = ShaderDb::from_file(&file.0).unwrap();
+        let report = db.merge_from(&other.0).unwrap();
+        assert_eq!(report.bytes_imported, 1);
+        assert_eq!(report.disasm_imported, 1);
+        assert_eq!(file_version(&other.0), 1);
+
+        let missing = TempDb::new("merge_missing");
+        assert!(db.merge_from(&missing.0).is_err());
+        assert!(!missing.0.exists());
+    }
+
+    #[test]
+    fn merge_keeps_elfs_from_different_compilers() {
+        let compiler = |dll: &[u8]| CompilerIdentity {
+            sha256: sha256(dll),
+            file_version: None,
+            path: "atidxx64.dll".to_owned(),
+        };
+        let file = TempDb::new("merge_elf_main");
+        let other_file = TempDb::new("merge_elf_other");
+
+        let mut db = ShaderDb::from_file(&file.0).unwrap();
+        let id = db.insert_compiler_identity(&compiler(b"old driver")).unwrap();
+        db.insert_bytes("chara", "skin", ShaderStage::Vertex, BytesType::ELF, b"old elf", false, Some(AsicTarget::RDNA2), Some(id)).unwrap();
+
+        let mut other = ShaderDb::from_file(&other_file.0).unwrap();
+        let id = other.insert_compiler_identity(&compiler(b"new driver")).unwrap();
+        other.insert_bytes("chara", "skin", ShaderStage::Vertex, BytesType::ELF, b"new elf", false, Some(AsicTarget::RDNA2), Some(id)).unwrap();
+        drop(other);
+
+        let report = db.merge_from(&other_file.0).unwrap();
+        assert!(report.conflicts.is_empty());
+        assert_eq!(report.bytes_imported, 1);
+        let rows = db.query_bytes(&ShaderFilter::default()).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().any(|row| row.bytes == b"new elf" && row.compiler == Some(compiler(b"new driver"))));
+    }
 }
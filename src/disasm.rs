@@ -7,6 +7,8 @@ use turnip_gfx_disasm::{
     Decoder, Program, hlsl::{compat::{HLSLCompatibleAbstractVM, program_to_hlsl}, display::DWrap, kinds::{HLSLKindBitmask, HLSLKind}, vm::HLSLAbstractVM}
 };
 
+use crate::dxbc::ShaderInterface;
+
 // pub fn disassemble_rdna2(rdna2: &[u8]) -> Result<RDNA2Program, RDNA2DecodeError> {
 //     RDNA2Decoder::new().decode(rdna2)
 // }
@@ -21,6 +23,20 @@ pub fn analyze_program<T: HLSLCompatibleAbstractVM>(program: &impl Program<T>) {
 }
 
 pub fn print_output_depedencies<T: HLSLCompatibleAbstractVM>(program: &impl Program<T>) {
+    print!("{}", output_dependencies_report(program, None));
+}
+
+/// Returns the report printed by [print_output_depedencies]: the IO declarations, what each output depends on,
+/// the textures each output samples, and the program text.
+///
+/// If the shader's `interface` is known, the textures, samplers and inputs are labelled with their names from the DXBC.
+pub fn output_dependencies_report<T: HLSLCompatibleAbstractVM>(program: &impl Program<T>, interface: Option<&ShaderInterface>) -> String {
+    let mut report = String::new();
+    write_output_dependencies(program, interface, &mut report).unwrap();
+    report
+}
+
+fn write_output_dependencies<T: HLSLCompatibleAbstractVM>(program: &impl Program<T>, interface: Option<&ShaderInterface>, w: &mut impl std::fmt::Write) -> std::fmt::Result {
     let program_compat = disassemble(&program_to_hlsl::<T, _>(program));
 
     let mut resolver = ScalarDependencies::<HLSLAbstractVM>::new();
@@ -28,13 +44,13 @@ pub fn print_output_depedencies<T: HLSLCompatibleAbstractVM>(program: &impl Prog
         resolver.accum_action(action, &HashSet::new());
     }
 
-    println!("Inputs and Outputs:");
+    writeln!(w, "Inputs and Outputs:")?;
     for r in program_compat.io_declarations() {
-        println!("\t{:?}", r);
+        writeln!(w, "\t{:?}", r)?;
     }
 
     for dependent in resolver.discard_dependencies {
-        println!("discard depends on {:?}", dependent)
+        writeln!(w, "discard depends on {:?}", dependent)?;
     }
 
     let mut out_deps: Vec<_> = resolver.dependents.iter().filter_map(|(out, deps)| {
@@ -77,17 +93,359 @@ pub fn print_output_depedencies<T: HLSLCompatibleAbstractVM>(program: &impl Prog
     out_deps.sort_by(|(out1, ..), (out2, ..)| out1.partial_cmp(out2).unwrap());
 
     for (out, vecs) in out_deps {
-        println!("{:?}.{} depends on {:?}", 
+        writeln!(w, "{:?}.{} depends on {:?}", 
             out.0, out.1,
             vecs
             // DisplayVec::Sep { vec: &(vecs.iter().map(|v| DWrap((v, v.output_kind()))).collect()), sep: ", " },
             // DisplayVec::Sep { vec: &(lits.into_iter().map(|l| DWrap(l))).collect(), sep: ", "}
-        )
+        )?;
+    }
+
+    writeln!(w, "Texture dependencies:")?;
+    for (output, samples) in output_texture_samples(program) {
+        if samples.is_empty() {
+            writeln!(w, "\t{output} doesn't sample any textures")?;
+        }
+        for sample in samples {
+            write!(w, "\t{output} samples {}", labelled_register(interface, &sample.texture))?;
+            if let Some(sampler) = &sample.sampler {
+                write!(w, " with {}", labelled_register(interface, sampler))?;
+            }
+            if sample.coordinate_inputs.is_empty() {
+                writeln!(w, " at constant coordinates")?;
+            } else {
+                let inputs: Vec<_> = sample.coordinate_inputs.iter().map(|input| labelled_scalar(interface, input)).collect();
+                writeln!(w, " at {}", inputs.join(", "))?;
+            }
+        }
     }
 
-    println!("PROGRAM TEXT BEGIN");
+    writeln!(w, "PROGRAM TEXT BEGIN")?;
     for a in program_compat.actions {
-        println!("{}", a);
+        writeln!(w, "{}", a)?;
+    }
+    Ok(())
+}
+
+/// Lifts a program into the HLSL-compatible VM and resolves its variables,
+/// returning the text of each action as a statement ending in `;` (or a brace).
+///
+/// This is the body used by the decompilers in [crate::hlsl] and [crate::glsl].
+pub fn lifted_statements<T: HLSLCompatibleAbstractVM>(program: &impl Program<T>) -> Vec<String> {
+    let program_compat = disassemble(&program_to_hlsl::<T, _>(program));
+    program_compat
+        .actions()
+        .iter()
+        .map(|action| {
+            let statement = action.to_string();
+            let statement = statement.trim_end();
+            if statement.ends_with([';', '{', '}']) {
+                statement.to_owned()
+            } else {
+                format!("{statement};")
+            }
+        })
+        .collect()
+}
+
+/// Runs the dependency analysis over the whole program
+fn program_dependencies<T: HLSLCompatibleAbstractVM>(program: &impl Program<T>) -> ScalarDependencies<HLSLAbstractVM> {
+    let program_compat = disassemble(&program_to_hlsl::<T, _>(program));
+    let mut resolver = ScalarDependencies::<HLSLAbstractVM>::new();
+    for action in program_compat.actions() {
+        resolver.accum_action(action, &HashSet::new());
+    }
+    resolver
+}
+
+/// The name of a scalar as shown in the dependency report, e.g. `o0.w`
+fn scalar_name(reg: &impl std::fmt::Debug, comp: &impl std::fmt::Display) -> String {
+    format!("{:?}.{}", reg, comp)
+}
+
+/// Compares scalar names ignoring case and whitespace, so `--slice O0.W` works
+fn scalar_name_matches(name: &str, query: &str) -> bool {
+    let normalize = |s: &str| s.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase();
+    normalize(name) == normalize(query)
+}
+
+/// How deeply each statement is nested in control flow, going by the braces at the start and end of its text.
+/// A statement which closes a block (e.g. `} else {`) counts as outside it.
+fn nesting_depths<'a>(statements: impl IntoIterator<Item = &'a str>) -> Vec<usize> {
+    let mut depth = 0_usize;
+    statements
+        .into_iter()
+        .map(|statement| {
+            let statement = statement.trim();
+            if statement.starts_with('}') {
+                depth = depth.saturating_sub(1);
+            }
+            let statement_depth = depth;
+            if statement.ends_with('{') {
+                depth += 1;
+            }
+            statement_depth
+        })
+        .collect()
+}
+
+/// What one action of a lifted program writes, for walking back through the program with [walk_back]
+struct ActionDeps<S> {
+    /// Each scalar the action writes, with the scalars it reads to compute it
+    writes: HashMap<S, HashSet<S>>,
+    /// How deeply the action is nested in control flow, see [nesting_depths]
+    depth: usize,
+}
+
+/// Runs `dependents` (the dependency analysis) over each action alone, returning the text of each action and what it writes
+fn action_dependencies<A, R, C, K, Deps, Reads>(
+    actions: &[A],
+    dependents: impl Fn(&A) -> Deps,
+) -> (Vec<String>, Vec<ActionDeps<(R, C)>>)
+where
+    A: std::fmt::Display,
+    R: Eq + std::hash::Hash,
+    C: Eq + std::hash::Hash,
+    Deps: IntoIterator<Item = ((R, C), Reads)>,
+    Reads: IntoIterator<Item = (R, C, K)>,
+{
+    let statements: Vec<_> = actions.iter().map(|action| action.to_string()).collect();
+    let depths = nesting_depths(statements.iter().map(String::as_str));
+    let deps = actions
+        .iter()
+        .zip(depths)
+        .map(|(action, depth)| ActionDeps {
+            writes: dependents(action)
+                .into_iter()
+                .map(|(written, reads)| (written, reads.into_iter().map(|(reg, comp, _kind)| (reg, comp)).collect()))
+                .collect(),
+            depth,
+        })
+        .collect();
+    (statements, deps)
+}
+
+/// Walks backwards from the action before `end`, returning the indices of the actions which write a needed scalar
+/// (latest first) and the needed scalars which no earlier action writes.
+///
+/// A write inside a branch might not happen, so it doesn't stop the walk looking for earlier writes to the same scalar.
+fn walk_back<S: Clone + Eq + std::hash::Hash>(actions: &[ActionDeps<S>], end: usize, mut needed: HashSet<S>) -> (Vec<usize>, HashSet<S>) {
+    let mut contributing = vec![];
+    for (i, action) in actions[..end].iter().enumerate().rev() {
+        if !action.writes.keys().any(|written| needed.contains(written)) {
+            continue;
+        }
+        contributing.push(i);
+        // All the writes of an action happen at once, so e.g. `r0.xy = r0.yx` still needs the earlier `r0.y`
+        let needed_writes: Vec<_> = action
+            .writes
+            .iter()
+            .filter(|(written, _)| needed.contains(*written))
+            .collect();
+        if action.depth == 0 {
+            for (written, _) in &needed_writes {
+                needed.remove(*written);
+            }
+        }
+        for (_, reads) in needed_writes {
+            needed.extend(reads.iter().cloned());
+        }
+    }
+    (contributing, needed)
+}
+
+/// Returns the text of each action which transitively contributes to the output scalar named `output`
+/// (as in the dependency report, e.g. `o0.w`), in program order, or None if no action writes to it.
+///
+/// The scalars each action reads and writes are found by running the dependency analysis over that action alone.
+/// Actions which don't write any scalars (e.g. control flow) are always kept so the structure of the program stays readable,
+/// but the conditions of branches aren't followed.
+/// A write inside a branch might not happen, so the earlier writes to the same scalar are kept as well.
+pub fn slice_program<T: HLSLCompatibleAbstractVM>(program: &impl Program<T>, output: &str) -> Option<Vec<String>> {
+    let program_compat = disassemble(&program_to_hlsl::<T, _>(program));
+    let empty_ctrl_flow = HashSet::new();
+    let (statements, actions) = action_dependencies(program_compat.actions(), |action| {
+        let mut resolver = ScalarDependencies::<HLSLAbstractVM>::new();
+        resolver.accum_action(action, &empty_ctrl_flow);
+        resolver.dependents
+    });
+
+    let target = actions
+        .iter()
+        .flat_map(|action| action.writes.keys())
+        .find(|written| written.0.is_output() && scalar_name_matches(&scalar_name(&written.0, &written.1), output))?
+        .clone();
+
+    let (contributing, _) = walk_back(&actions, actions.len(), HashSet::from([target]));
+    let contributing: HashSet<_> = contributing.into_iter().collect();
+    Some(
+        statements
+            .into_iter()
+            .zip(&actions)
+            .enumerate()
+            .filter(|(i, (_, action))| action.writes.is_empty() || contributing.contains(i))
+            .map(|(_, (statement, _))| statement)
+            .collect(),
+    )
+}
+
+/// Returns the names of the output scalars written by the program, for suggesting what to pass to [slice_program]
+pub fn output_scalar_names<T: HLSLCompatibleAbstractVM>(program: &impl Program<T>) -> Vec<String> {
+    let resolver = program_dependencies(program);
+    let mut outputs: Vec<_> = resolver.dependents.keys().filter(|out| out.0.is_output()).collect();
+    outputs.sort_by(|out1, out2| out1.partial_cmp(out2).unwrap());
+    outputs.into_iter().map(|out| scalar_name(&out.0, &out.1)).collect()
+}
+
+/// Checks if a scalar read by the program is named by `input`, which is either a scalar name (e.g. `v2.z`)
+/// or a register name matching all of its scalars (e.g. `v2`, or `cb0` for every element of the buffer)
+fn input_matches(reg: &impl std::fmt::Debug, comp: &impl std::fmt::Display, input: &str) -> bool {
+    let reg_name = format!("{:?}", reg);
+    scalar_name_matches(&scalar_name(reg, comp), input)
+        || scalar_name_matches(&reg_name, input)
+        || reg_name
+            .split_once('[')
+            .is_some_and(|(array, _)| scalar_name_matches(array, input))
+}
+
+/// Returns the names of the output scalars which depend on any of `inputs`, in the order of the dependency report.
+///
+/// This is the reverse of the dependency report: each input is a register or scalar name as accepted by [input_matches],
+/// e.g. from [crate::dxbc::ShaderInterface::resolve_input_name].
+pub fn outputs_depending_on<T: HLSLCompatibleAbstractVM>(program: &impl Program<T>, inputs: &[String]) -> Vec<String> {
+    let resolver = program_dependencies(program);
+    let mut outputs: Vec<_> = resolver
+        .dependents
+        .iter()
+        .filter(|(out, deps)| {
+            out.0.is_output()
+                && deps
+                    .iter()
+                    .any(|(reg, comp, _kind)| inputs.iter().any(|input| input_matches(reg, comp, input)))
+        })
+        .map(|(out, _)| out)
+        .collect();
+    outputs.sort_by(|out1, out2| out1.partial_cmp(out2).unwrap());
+    outputs.into_iter().map(|out| scalar_name(&out.0, &out.1)).collect()
+}
+
+/// A texture sample which an output depends on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextureSample {
+    /// The texture register, e.g. `t3`
+    pub texture: String,
+    /// The sampler register, e.g. `s0`, or None for loads which don't use one
+    pub sampler: Option<String>,
+    /// The scalars read by the program which the coordinates are computed from, e.g. `v1.x` or `cb0[2].y`, sorted by name
+    pub coordinate_inputs: Vec<String>,
+}
+
+/// Checks if a register name is a resource register with the given prefix, e.g. `t3` for `t`
+fn is_resource_register(reg_name: &str, prefix: &str) -> bool {
+    reg_name
+        .strip_prefix(prefix)
+        .is_some_and(|index| !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Returns each output scalar (named as in the dependency report, e.g. `o0.x`) with the texture samples it transitively
+/// depends on, in program order.
+///
+/// An action is treated as a sample if it reads a `t#` register, in which case the `s#` register it reads is the sampler
+/// and everything else it reads is a coordinate. The coordinates are then followed back to the program inputs,
+/// so a UV computed from `v1.xy` and a constant reports `v1.x`, `v1.y` and the constant.
+/// Like [slice_program], this runs the dependency analysis over each action alone, doesn't follow branch conditions,
+/// and keeps following a scalar back past a write to it inside a branch.
+pub fn output_texture_samples<T: HLSLCompatibleAbstractVM>(program: &impl Program<T>) -> Vec<(String, Vec<TextureSample>)> {
+    let program_compat = disassemble(&program_to_hlsl::<T, _>(program));
+    let empty_ctrl_flow = HashSet::new();
+    let (_, actions) = action_dependencies(program_compat.actions(), |action| {
+        let mut resolver = ScalarDependencies::<HLSLAbstractVM>::new();
+        resolver.accum_action(action, &empty_ctrl_flow);
+        resolver.dependents
+    });
+
+    // The sample performed by each action, if any, found when an output first depends on it
+    let mut samples: HashMap<usize, Option<TextureSample>> = HashMap::new();
+    let mut sample_at = |i: usize| {
+        samples
+            .entry(i)
+            .or_insert_with(|| {
+                let reads: Vec<_> = actions[i].writes.values().flatten().collect();
+                let reg_names = || reads.iter().map(|(reg, _comp)| format!("{:?}", reg));
+                let texture = reg_names().find(|name| is_resource_register(name, "t"))?;
+                let sampler = reg_names().find(|name| is_resource_register(name, "s"));
+                let coordinates = reads
+                    .iter()
+                    .filter(|(reg, _comp)| {
+                        let name = format!("{:?}", reg);
+                        !is_resource_register(&name, "t") && !is_resource_register(&name, "s")
+                    })
+                    .map(|&scalar| scalar.clone())
+                    .collect();
+                let (_, inputs) = walk_back(&actions, i, coordinates);
+                let mut coordinate_inputs: Vec<_> = inputs
+                    .iter()
+                    .filter(|(reg, _comp)| {
+                        let name = format!("{:?}", reg);
+                        !is_resource_register(&name, "t") && !is_resource_register(&name, "s")
+                    })
+                    .map(|(reg, comp)| scalar_name(reg, comp))
+                    .collect();
+                coordinate_inputs.sort();
+                Some(TextureSample {
+                    texture,
+                    sampler,
+                    coordinate_inputs,
+                })
+            })
+            .clone()
+    };
+
+    let mut outputs: Vec<_> = actions
+        .iter()
+        .flat_map(|action| action.writes.keys())
+        .filter(|written| written.0.is_output())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    outputs.sort_by(|out1, out2| out1.partial_cmp(out2).unwrap());
+
+    outputs
+        .into_iter()
+        .map(|out| {
+            let (contributing, _) = walk_back(&actions, actions.len(), HashSet::from([out.clone()]));
+            let mut out_samples = vec![];
+            for i in contributing.into_iter().rev() {
+                if let Some(sample) = sample_at(i) {
+                    if !out_samples.contains(&sample) {
+                        out_samples.push(sample);
+                    }
+                }
+            }
+            (scalar_name(&out.0, &out.1), out_samples)
+        })
+        .collect()
+}
+
+/// Labels a resource register with its name from the reflection data, e.g. `t3 (g_AlbedoTexture)`
+fn labelled_register(interface: Option<&ShaderInterface>, register: &str) -> String {
+    match interface.and_then(|interface| interface.resource_name(register)) {
+        Some(name) => format!("{register} ({name})"),
+        None => register.to_owned(),
+    }
+}
+
+/// Labels an input scalar with the semantic of the signature element it belongs to, e.g. `v1.x (TEXCOORD0)`
+fn labelled_scalar(interface: Option<&ShaderInterface>, scalar: &str) -> String {
+    let element = interface.zip(scalar.split_once('.')).and_then(|(interface, (register, comp))| {
+        interface
+            .inputs
+            .iter()
+            .find(|element| element.register_name("v") == register && element.swizzle().contains(comp))
+    });
+    match element {
+        Some(element) => format!("{scalar} ({})", element.field_name()),
+        None => scalar.to_owned(),
     }
 }
 
@@ -154,4 +512,203 @@ pub fn resolve_variable_text_dependencies<T: AbstractVM>(program: &impl Program<
         }
     }
 }
-*/
\ No newline at end of file
+*/
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Writes `o0.x` from `r0.x`, which is only overwritten when `v1.x` is set, and `o0.y` from `v1.y`
+    const BRANCHING_PROGRAM: &str = "il_ps_2_0
+dcl_input_generic_interp(linear) v0
+dcl_input_generic_interp(linear) v1
+dcl_output_generic o0
+mov r0.x___, v0.x
+if_logicalnz v1.x
+    mov r0.x___, v0.y
+endif
+mov o0.x___, r0.x
+mov o0._y__, v1.y
+ret_dyn
+end
+";
+
+    #[test]
+    fn nesting_depths_follow_braces() {
+        let statements = ["r0.x = v0.x;", "if (v1.x) {", "r0.x = v0.y;", "} else {", "r0.x = v0.z;", "}", "o0.x = r0.x;"];
+        assert_eq!(nesting_depths(statements), [0, 0, 1, 0, 1, 0, 0]);
+    }
+
+    fn action(depth: usize, writes: &[(&'static str, &[&'static str])]) -> ActionDeps<&'static str> {
+        ActionDeps {
+            writes: writes
+                .iter()
+                .map(|(written, reads)| (*written, reads.iter().copied().collect()))
+                .collect(),
+            depth,
+        }
+    }
+
+    #[test]
+    fn walk_back_through_swizzled_self_reads() {
+        // Repeated because the writes of one action are visited in HashMap order, which is seeded per map
+        for _ in 0..16 {
+            // r0.xy = r0.yx, where each write reads the other's old value
+            let actions = [
+                action(0, &[("r0.x", &["v0.x"])]),
+                action(0, &[("r0.y", &["v0.y"])]),
+                action(0, &[("r0.x", &["r0.y"]), ("r0.y", &["r0.x"])]),
+                action(0, &[("o0.x", &["r0.x"])]),
+            ];
+            let (contributing, inputs) = walk_back(&actions, actions.len(), HashSet::from(["o0.x"]));
+            assert_eq!(contributing, [3, 2, 1]);
+            assert_eq!(inputs, HashSet::from(["v0.y"]));
+        }
+    }
+
+    #[test]
+    fn walk_back_past_branches() {
+        let actions = [
+            action(0, &[("r0.x", &["v0.x"])]),
+            action(0, &[("r0.y", &["v0.y"])]),
+            action(0, &[]),
+            action(1, &[("r0.x", &["v1.x"])]),
+            action(0, &[]),
+            action(0, &[("r1.x", &["r0.x", "r0.y"])]),
+            action(0, &[("r0.y", &["v2.y"])]),
+            action(0, &[("o0.x", &["r1.x"])]),
+        ];
+
+        let (contributing, inputs) = walk_back(&actions, actions.len(), HashSet::from(["o0.x"]));
+        assert_eq!(contributing, [7, 5, 3, 1, 0]);
+        assert_eq!(inputs, HashSet::from(["v0.x", "v0.y", "v1.x"]));
+
+        // Walking back from before the branch only sees the first writes
+        let (contributing, inputs) = walk_back(&actions, 2, HashSet::from(["r0.x", "r2.x"]));
+        assert_eq!(contributing, [0]);
+        assert_eq!(inputs, HashSet::from(["v0.x", "r2.x"]));
+    }
+
+    #[test]
+    fn slice_keeps_writes_before_branches() {
+        let program = disassemble_amdil_text(BRANCHING_PROGRAM.as_bytes()).unwrap();
+        let statements = lifted_statements(&program);
+        let slice = slice_program(&program, "o0.x").unwrap();
+
+        // Only the write to o0.y is dropped, the write to r0.x before the branch is still needed
+        assert_eq!(slice.len(), statements.len() - 1);
+        assert!(slice_program(&program, "o1.x").is_none());
+    }
+
+    #[test]
+    fn outputs_depending_on_inputs() {
+        let program = disassemble_amdil_text(BRANCHING_PROGRAM.as_bytes()).unwrap();
+
+        assert_eq!(outputs_depending_on(&program, &["v0".to_owned()]), ["o0.x"]);
+        assert_eq!(outputs_depending_on(&program, &["v1.y".to_owned()]), ["o0.y"]);
+        assert!(outputs_depending_on(&program, &["v2".to_owned()]).is_empty());
+    }
+
+    #[test]
+    fn texture_samples_of_outputs() {
+        let program = disassemble_amdil_text(
+            b"il_ps_2_0
+dcl_resource_id(3)_type(2d)_fmtx(float)_fmty(float)_fmtz(float)_fmtw(float)
+dcl_sampler s0
+dcl_input_generic_interp(linear) v1
+dcl_output_generic o0
+sample_resource(3)_sampler(0) r0, v1.xy00
+mov o0.x___, r0.x
+mov o0._y__, v1.z
+ret_dyn
+end
+",
+        )
+        .unwrap();
+        let samples = output_texture_samples(&program);
+
+        assert_eq!(
+            samples,
+            [
+                (
+                    "o0.x".to_owned(),
+                    vec![TextureSample {
+                        texture: "t3".to_owned(),
+                        sampler: Some("s0".to_owned()),
+                        coordinate_inputs: vec!["v1.x".to_owned(), "v1.y".to_owned()],
+                    }]
+                ),
+                ("o0.y".to_owned(), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn texture_sample_coordinates_resolve_to_inputs() {
+        let program = disassemble_amdil_text(
+            b"il_ps_2_0
+dcl_resource_id(3)_type(2d)_fmtx(float)_fmty(float)_fmtz(float)_fmtw(float)
+dcl_sampler s0
+dcl_cb cb0[1]
+dcl_input_generic_interp(linear) v1
+dcl_input_generic_interp(linear) v2
+dcl_output_generic o0
+mad_ieee r1.xy__, v1.xy00, cb0[0].xy00, cb0[0].zw00
+if_logicalnz v2.x
+    mov r1.x___, v1.z
+endif
+sample_resource(3)_sampler(0) r0, r1.xy00
+mov o0, r0
+ret_dyn
+end
+",
+        )
+        .unwrap();
+        let samples = output_texture_samples(&program);
+
+        let sample = TextureSample {
+            texture: "t3".to_owned(),
+            sampler: Some("s0".to_owned()),
+            // v1.x still counts, as the branch might not overwrite r1.x
+            coordinate_inputs: ["cb0[0].x", "cb0[0].y", "cb0[0].z", "cb0[0].w", "v1.x", "v1.y", "v1.z"]
+                .map(str::to_owned)
+                .to_vec(),
+        };
+        assert_eq!(samples.len(), 4);
+        for (output, output_samples) in samples {
+            assert!(output.starts_with("o0."));
+            assert_eq!(output_samples, std::slice::from_ref(&sample));
+        }
+    }
+
+    #[test]
+    fn texture_sample_coordinates_swizzled_in_place() {
+        let program = disassemble_amdil_text(
+            b"il_ps_2_0
+dcl_resource_id(0)_type(2d)_fmtx(float)_fmty(float)_fmtz(float)_fmtw(float)
+dcl_sampler s1
+dcl_input_generic_interp(linear) v0
+dcl_output_generic o0
+mov r0.x___, v0.x
+mov r0._y__, v0.w
+mov r0.xy__, r0.yx00
+sample_resource(0)_sampler(1) r1, r0.xy00
+mov o0.x___, r1.x
+ret_dyn
+end
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            output_texture_samples(&program),
+            [(
+                "o0.x".to_owned(),
+                vec![TextureSample {
+                    texture: "t0".to_owned(),
+                    sampler: Some("s1".to_owned()),
+                    coordinate_inputs: vec!["v0.w".to_owned(), "v0.x".to_owned()],
+                }]
+            )]
+        );
+    }
+}
@@ -0,0 +1,497 @@
+//! This module reads the parts of DirectX ByteCode containers which are needed to analyse shaders,
+//! independently of the compiler.
+//!
+//! A DXBC container is a header followed by a list of chunks, each identified by a FourCC.
+//! The shader program itself is in the `SHDR` (SM4) or `SHEX` (SM5) chunk,
+//! which starts with a version token giving the program type and shader model.
+//!
+//! The interface of the shader is described by the `ISGN`/`OSGN` signature chunks (or `OSG5` for geometry shaders)
+//! and the `RDEF` reflection chunk, which are gathered into a [ShaderInterface] for the decompilers.
+//! Offsets inside those chunks are relative to the start of the chunk.
+
+use nom::{
+    bytes::complete::{tag, take},
+    multi::count,
+    number::complete::le_u32,
+    sequence::tuple,
+    IResult,
+};
+
+use crate::{db::ShaderStage, yk::YkGfxError};
+
+/// A chunk of a DXBC container
+pub struct DxbcChunk<'a> {
+    pub fourcc: [u8; 4],
+    pub data: &'a [u8],
+}
+
+/// Reads the list of chunks in a DXBC container
+pub fn parse_dxbc_chunks<'a>(
+    overall: &'a [u8],
+) -> IResult<&'a [u8], Vec<DxbcChunk<'a>>, YkGfxError<&'a [u8]>> {
+    let (input, (_magic, _checksum, _one, _total_len, chunk_count)) =
+        tuple((tag(b"DXBC"), take(16_usize), le_u32, le_u32, le_u32))(overall)?;
+    let (input, chunk_offsets) = count(le_u32, chunk_count as usize)(input)?;
+
+    let chunks = chunk_offsets
+        .into_iter()
+        .map(|offset| {
+            let chunk_start = overall.get(offset as usize..).unwrap_or_default();
+            let (chunk_input, (fourcc, chunk_len)) = tuple((take(4_usize), le_u32))(chunk_start)?;
+            let (_, data) = take(chunk_len as usize)(chunk_input)?;
+            Ok(DxbcChunk {
+                fourcc: fourcc.try_into().unwrap(),
+                data,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((input, chunks))
+}
+
+/// Returns the data of the first chunk with the given FourCC, if the container is valid and has one
+pub fn find_chunk<'a>(dxbc: &'a [u8], fourcc: &[u8; 4]) -> Option<&'a [u8]> {
+    let (_, chunks) = parse_dxbc_chunks(dxbc).ok()?;
+    chunks
+        .into_iter()
+        .find(|chunk| &chunk.fourcc == fourcc)
+        .map(|chunk| chunk.data)
+}
+
+/// The decoded version token at the start of a shader program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramVersion {
+    pub stage: ShaderStage,
+    pub major: u8,
+    pub minor: u8,
+}
+
+/// Decodes the program version token from the `SHEX` or `SHDR` chunk,
+/// returning None if the container doesn't have one or it declares an unknown program type.
+pub fn program_version(dxbc: &[u8]) -> Option<ProgramVersion> {
+    let program = find_chunk(dxbc, b"SHEX").or_else(|| find_chunk(dxbc, b"SHDR"))?;
+    let token = u32::from_le_bytes(program.get(0..4)?.try_into().unwrap());
+
+    let stage = match token >> 16 {
+        0 => ShaderStage::Fragment,
+        1 => ShaderStage::Vertex,
+        2 => ShaderStage::Geometry,
+        3 => ShaderStage::Hull,
+        4 => ShaderStage::Domain,
+        5 => ShaderStage::Compute,
+        _ => return None,
+    };
+    Some(ProgramVersion {
+        stage,
+        major: ((token >> 4) & 0xF) as u8,
+        minor: (token & 0xF) as u8,
+    })
+}
+
+/// Reads a little-endian u32 at `offset` in `data`
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().unwrap()))
+}
+
+/// Reads a little-endian u16 at `offset` in `data`
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().unwrap()))
+}
+
+/// Reads a null-terminated string at `offset` in `data`
+fn string_at(data: &[u8], offset: usize) -> Option<String> {
+    let bytes = data.get(offset..)?;
+    let len = bytes.iter().position(|b| *b == 0)?;
+    Some(String::from_utf8_lossy(&bytes[..len]).into_owned())
+}
+
+/// The type of each component of a signature element
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentType {
+    UInt,
+    SInt,
+    Float,
+    Unknown(u32),
+}
+
+/// An input or output of a shader stage
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureElement {
+    pub semantic_name: String,
+    pub semantic_index: u32,
+    /// The D3D_NAME system value, or 0 for none
+    pub system_value: u32,
+    pub component_type: ComponentType,
+    /// The `v#` or `o#` register the element is packed into
+    pub register: u32,
+    /// The components of the register used by the element, as a bitmask of xyzw
+    pub mask: u8,
+}
+impl SignatureElement {
+    /// The number of components in the element
+    pub fn width(&self) -> u32 {
+        self.mask.count_ones()
+    }
+
+    /// A name for the element in generated source, e.g. `TEXCOORD1`
+    pub fn field_name(&self) -> String {
+        format!("{}{}", self.semantic_name, self.semantic_index)
+    }
+
+    /// The register holding the element, e.g. `v3` for prefix `v`.
+    /// Elements which don't use a numbered register, like SV_Depth, get a register named after them.
+    pub fn register_name(&self, prefix: &str) -> String {
+        if self.register == u32::MAX {
+            format!("{prefix}{}", self.semantic_name)
+        } else {
+            format!("{prefix}{}", self.register)
+        }
+    }
+
+    /// The swizzle selecting the element's components from its register, e.g. `zw`
+    pub fn swizzle(&self) -> String {
+        "xyzw"
+            .chars()
+            .enumerate()
+            .filter(|(i, _)| self.mask & (1 << i) != 0)
+            .map(|(_, c)| c)
+            .collect()
+    }
+}
+
+/// Parses an `ISGN`, `OSGN` or `OSG5` chunk.
+/// `OSG5` elements have a leading stream index, which is skipped.
+pub fn parse_signature(chunk: &[u8], has_stream: bool) -> Option<Vec<SignatureElement>> {
+    let count = u32_at(chunk, 0)? as usize;
+    let (stride, start) = if has_stream { (28, 4) } else { (24, 0) };
+    (0..count)
+        .map(|i| {
+            let element = 8 + i * stride + start;
+            Some(SignatureElement {
+                semantic_name: string_at(chunk, u32_at(chunk, element)? as usize)?,
+                semantic_index: u32_at(chunk, element + 4)?,
+                system_value: u32_at(chunk, element + 8)?,
+                component_type: match u32_at(chunk, element + 12)? {
+                    1 => ComponentType::UInt,
+                    2 => ComponentType::SInt,
+                    3 => ComponentType::Float,
+                    other => ComponentType::Unknown(other),
+                },
+                register: u32_at(chunk, element + 16)?,
+                mask: *chunk.get(element + 20)?,
+            })
+        })
+        .collect()
+}
+
+/// The type of a constant buffer variable, from the `RDEF` chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableType {
+    /// The D3D_SHADER_VARIABLE_CLASS, e.g. 1 for vectors or 5 for structs
+    pub class: u16,
+    /// The D3D_SHADER_VARIABLE_TYPE, e.g. 3 for float
+    pub base: u16,
+    pub rows: u16,
+    pub columns: u16,
+    /// The array length, or 0 if it isn't an array
+    pub elements: u16,
+    /// The name, offset and type of each member of a struct
+    pub members: Vec<(String, u32, VariableType)>,
+}
+
+/// A variable in a constant buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstantVariable {
+    pub name: String,
+    /// Offset from the start of the buffer in bytes
+    pub offset: u32,
+    pub size: u32,
+    pub ty: VariableType,
+}
+
+impl ConstantVariable {
+    /// Where the variable is in the registers of a buffer bound to `cb{register}`, e.g. `cb0[2].yz` or `cb0[4..7]`
+    pub fn location(&self, register: u32) -> String {
+        let first = self.offset / 16;
+        let last = (self.offset.saturating_add(self.size.max(1)) - 1) / 16;
+        if first != last {
+            return format!("cb{register}[{first}..{last}]");
+        }
+        let start = (self.offset % 16 / 4) as usize;
+        let count = (self.size.div_ceil(4) as usize).clamp(1, 4 - start);
+        format!("cb{register}[{first}].{}", &"xyzw"[start..start + count])
+    }
+
+    /// The name of every scalar the variable occupies in a buffer bound to `cb{register}`, e.g. `cb0[2].y`
+    pub fn scalar_names(&self, register: u32) -> Vec<String> {
+        (self.offset..self.offset.saturating_add(self.size.max(1)))
+            .step_by(4)
+            .map(|offset| format!("cb{register}[{}].{}", offset / 16, ["x", "y", "z", "w"][(offset % 16 / 4) as usize]))
+            .collect()
+    }
+}
+
+/// A constant buffer declared in the `RDEF` chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstantBuffer {
+    pub name: String,
+    /// Size in bytes, always a multiple of 16
+    pub size: u32,
+    pub variables: Vec<ConstantVariable>,
+}
+
+/// The kinds of resource a shader can bind (D3D_SHADER_INPUT_TYPE)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    ConstantBuffer,
+    TextureBuffer,
+    Texture,
+    Sampler,
+    /// Any kind of UAV
+    UnorderedAccess,
+    /// Structured and byte-address buffers
+    Buffer,
+    Unknown(u32),
+}
+impl ResourceKind {
+    fn from_input_type(input_type: u32) -> Self {
+        match input_type {
+            0 => ResourceKind::ConstantBuffer,
+            1 => ResourceKind::TextureBuffer,
+            2 => ResourceKind::Texture,
+            3 => ResourceKind::Sampler,
+            4 | 6 | 8 | 9 | 10 | 11 => ResourceKind::UnorderedAccess,
+            5 | 7 => ResourceKind::Buffer,
+            other => ResourceKind::Unknown(other),
+        }
+    }
+
+    /// The register prefix for resources of this kind, e.g. `t` for textures
+    pub fn register_prefix(self) -> &'static str {
+        match self {
+            ResourceKind::ConstantBuffer => "cb",
+            ResourceKind::Sampler => "s",
+            ResourceKind::UnorderedAccess => "u",
+            ResourceKind::TextureBuffer | ResourceKind::Texture | ResourceKind::Buffer | ResourceKind::Unknown(_) => "t",
+        }
+    }
+}
+
+/// A resource bound to the shader, from the `RDEF` chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceBinding {
+    pub name: String,
+    pub kind: ResourceKind,
+    /// The D3D_RESOURCE_RETURN_TYPE of a texture, e.g. 5 for float
+    pub return_type: u32,
+    /// The D3D_SRV_DIMENSION of a texture, e.g. 4 for 2D
+    pub dimension: u32,
+    pub bind_point: u32,
+    pub bind_count: u32,
+    /// The number of components returned by a texture
+    pub components: u32,
+}
+
+/// The constant buffers and resources described by the `RDEF` chunk
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reflection {
+    pub constant_buffers: Vec<ConstantBuffer>,
+    pub bindings: Vec<ResourceBinding>,
+}
+impl Reflection {
+    /// The register a constant buffer is bound to
+    pub fn constant_buffer_register(&self, buffer: &ConstantBuffer) -> Option<u32> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.kind == ResourceKind::ConstantBuffer && binding.name == buffer.name)
+            .map(|binding| binding.bind_point)
+    }
+}
+
+fn parse_variable_type(rdef: &[u8], offset: usize, depth: u32) -> Option<VariableType> {
+    // Guard against cyclic member lists in broken files
+    if depth > 16 {
+        return None;
+    }
+    let member_count = u16_at(rdef, offset + 10)? as usize;
+    let member_offset = u32_at(rdef, offset + 12)? as usize;
+    let members = (0..member_count)
+        .map(|i| {
+            let member = member_offset + i * 12;
+            Some((
+                string_at(rdef, u32_at(rdef, member)? as usize)?,
+                u32_at(rdef, member + 8)?,
+                parse_variable_type(rdef, u32_at(rdef, member + 4)? as usize, depth + 1)?,
+            ))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(VariableType {
+        class: u16_at(rdef, offset)?,
+        base: u16_at(rdef, offset + 2)?,
+        rows: u16_at(rdef, offset + 4)?,
+        columns: u16_at(rdef, offset + 6)?,
+        elements: u16_at(rdef, offset + 8)?,
+        members,
+    })
+}
+
+/// Parses an `RDEF` chunk
+pub fn parse_reflection(rdef: &[u8]) -> Option<Reflection> {
+    let buffer_count = u32_at(rdef, 0)? as usize;
+    let buffer_offset = u32_at(rdef, 4)? as usize;
+    let binding_count = u32_at(rdef, 8)? as usize;
+    let binding_offset = u32_at(rdef, 12)? as usize;
+    let major = *rdef.get(17)?;
+    // Shader model 5 variables have extra texture and sampler fields
+    let variable_stride = if major >= 5 { 40 } else { 24 };
+
+    let constant_buffers = (0..buffer_count)
+        .map(|i| {
+            let buffer = buffer_offset + i * 24;
+            let variable_count = u32_at(rdef, buffer + 4)? as usize;
+            let variable_offset = u32_at(rdef, buffer + 8)? as usize;
+            let variables = (0..variable_count)
+                .map(|j| {
+                    let variable = variable_offset + j * variable_stride;
+                    Some(ConstantVariable {
+                        name: string_at(rdef, u32_at(rdef, variable)? as usize)?,
+                        offset: u32_at(rdef, variable + 4)?,
+                        size: u32_at(rdef, variable + 8)?,
+                        ty: parse_variable_type(rdef, u32_at(rdef, variable + 16)? as usize, 0)?,
+                    })
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(ConstantBuffer {
+                name: string_at(rdef, u32_at(rdef, buffer)? as usize)?,
+                size: u32_at(rdef, buffer + 12)?,
+                variables,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let bindings = (0..binding_count)
+        .map(|i| {
+            let binding = binding_offset + i * 32;
+            let flags = u32_at(rdef, binding + 28)?;
+            Some(ResourceBinding {
+                name: string_at(rdef, u32_at(rdef, binding)? as usize)?,
+                kind: ResourceKind::from_input_type(u32_at(rdef, binding + 4)?),
+                return_type: u32_at(rdef, binding + 8)?,
+                dimension: u32_at(rdef, binding + 12)?,
+                bind_point: u32_at(rdef, binding + 20)?,
+                bind_count: u32_at(rdef, binding + 24)?,
+                // D3D_SIF_TEXTURE_COMPONENTS
+                components: ((flags >> 2) & 3) + 1,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(Reflection {
+        constant_buffers,
+        bindings,
+    })
+}
+
+/// Everything the decompilers need to know about the interface of a shader
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderInterface {
+    pub stage: ShaderStage,
+    pub inputs: Vec<SignatureElement>,
+    pub outputs: Vec<SignatureElement>,
+    pub reflection: Reflection,
+}
+impl ShaderInterface {
+    /// Expands the name of something the shader reads into the register or scalar names used in the dependency report.
+    ///
+    /// A constant buffer variable becomes the scalars it occupies (e.g. `cb0[4].x`), a constant buffer or other resource
+    /// becomes every register it's bound to (e.g. `cb0`, or `t3` and `t4` for an array of two textures),
+    /// and anything else is assumed to already be a register or scalar name.
+    pub fn resolve_input_name(&self, name: &str) -> Vec<String> {
+        for buffer in &self.reflection.constant_buffers {
+            let Some(register) = self.reflection.constant_buffer_register(buffer) else {
+                continue;
+            };
+            if let Some(variable) = buffer.variables.iter().find(|variable| variable.name == name) {
+                return variable.scalar_names(register);
+            }
+        }
+        if let Some(binding) = self.reflection.bindings.iter().find(|binding| binding.name == name) {
+            return (binding.bind_point..binding.bind_point.saturating_add(binding.bind_count.max(1)))
+                .map(|register| format!("{}{register}", binding.kind.register_prefix()))
+                .collect();
+        }
+        vec![name.to_owned()]
+    }
+
+    /// The name of the resource bound to a register from the dependency report (e.g. `t3` or `s0`),
+    /// if the reflection data describes it
+    pub fn resource_name(&self, register: &str) -> Option<&str> {
+        self.reflection
+            .bindings
+            .iter()
+            .find(|binding| {
+                register
+                    .strip_prefix(binding.kind.register_prefix())
+                    .and_then(|index| index.parse::<u32>().ok())
+                    .is_some_and(|index| (binding.bind_point..binding.bind_point.saturating_add(binding.bind_count.max(1))).contains(&index))
+            })
+            .map(|binding| binding.name.as_str())
+    }
+
+    /// Reads the interface of a shader from its DXBC.
+    /// Missing or unreadable chunks are treated as empty, as the game's shaders are sometimes stripped.
+    pub fn from_dxbc(dxbc: &[u8], stage: ShaderStage) -> Self {
+        let outputs = match find_chunk(dxbc, b"OSG5") {
+            Some(chunk) => parse_signature(chunk, true),
+            None => find_chunk(dxbc, b"OSGN").and_then(|chunk| parse_signature(chunk, false)),
+        };
+        Self {
+            stage,
+            inputs: find_chunk(dxbc, b"ISGN")
+                .and_then(|chunk| parse_signature(chunk, false))
+                .unwrap_or_default(),
+            outputs: outputs.unwrap_or_default(),
+            reflection: find_chunk(dxbc, b"RDEF")
+                .and_then(parse_reflection)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn interface(bindings: Vec<ResourceBinding>) -> ShaderInterface {
+        ShaderInterface {
+            stage: ShaderStage::Fragment,
+            inputs: vec![],
+            outputs: vec![],
+            reflection: Reflection { constant_buffers: vec![], bindings },
+        }
+    }
+
+    fn binding(name: &str, kind: ResourceKind, bind_point: u32, bind_count: u32) -> ResourceBinding {
+        ResourceBinding {
+            name: name.to_owned(),
+            kind,
+            return_type: 0,
+            dimension: 0,
+            bind_point,
+            bind_count,
+            components: 4,
+        }
+    }
+
+    #[test]
+    fn resource_arrays_resolve_to_every_register() {
+        let interface = interface(vec![
+            binding("g_Shadow", ResourceKind::Texture, 3, 2),
+            binding("g_Sampler", ResourceKind::Sampler, 0, 0),
+        ]);
+        assert_eq!(interface.resolve_input_name("g_Shadow"), ["t3", "t4"]);
+        assert_eq!(interface.resolve_input_name("g_Sampler"), ["s0"]);
+        assert_eq!(interface.resource_name("t4"), Some("g_Shadow"));
+        assert_eq!(interface.resolve_input_name("r0.x"), ["r0.x"]);
+    }
+}
@@ -0,0 +1,653 @@
+//! This module renders a decoded shader program as GLSL 4.50, for the material previewer.
+//!
+//! The layout follows [crate::hlsl]: constant buffers become `std140` uniform blocks holding a raw `vec4` array
+//! named after the register, inputs are copied into `v#` registers before the body and outputs copied out of
+//! `o#` registers after it. Inputs and outputs use the signature register as their location,
+//! with a component qualifier when several elements are packed into one register
+//! (elements of different types packed together are split, see `assign_locations`).
+//!
+//! The body is the program from [lifted_statements], which is written in HLSL syntax.
+//! HLSL type and intrinsic names are renamed to their GLSL equivalents, and the intrinsics GLSL doesn't have
+//! (`saturate`, `asfloat`...) are defined at the top of the file.
+//!
+//! GLSL combines textures and samplers, so textures are declared as `sampler*` uniforms and samplers only listed.
+
+use std::fmt::Write;
+
+use turnip_gfx_disasm::{hlsl::compat::HLSLCompatibleAbstractVM, Program};
+
+use crate::{
+    db::ShaderStage,
+    disasm::lifted_statements,
+    dxbc::{ComponentType, ResourceBinding, ResourceKind, ShaderInterface, SignatureElement, VariableType},
+};
+
+/// HLSL identifiers with a direct GLSL equivalent
+const RENAMES: &[(&str, &str)] = &[
+    ("float2", "vec2"),
+    ("float3", "vec3"),
+    ("float4", "vec4"),
+    ("int2", "ivec2"),
+    ("int3", "ivec3"),
+    ("int4", "ivec4"),
+    ("uint2", "uvec2"),
+    ("uint3", "uvec3"),
+    ("uint4", "uvec4"),
+    ("bool2", "bvec2"),
+    ("bool3", "bvec3"),
+    ("bool4", "bvec4"),
+    ("float2x2", "mat2"),
+    ("float3x3", "mat3"),
+    ("float4x4", "mat4"),
+    ("lerp", "mix"),
+    ("frac", "fract"),
+    ("rsqrt", "inversesqrt"),
+    ("ddx", "dFdx"),
+    ("ddy", "dFdy"),
+    ("ddx_coarse", "dFdxCoarse"),
+    ("ddy_coarse", "dFdyCoarse"),
+    ("ddx_fine", "dFdxFine"),
+    ("ddy_fine", "dFdyFine"),
+    ("atan2", "atan"),
+    ("mad", "fma"),
+    ("countbits", "bitCount"),
+    ("firstbithigh", "findMSB"),
+    ("firstbitlow", "findLSB"),
+    ("reversebits", "bitfieldReverse"),
+];
+
+/// HLSL texture methods and the GLSL functions replacing them.
+/// GLSL textures carry their own sampler, so the sampler argument is dropped.
+const TEXTURE_METHODS: &[(&str, &str)] = &[
+    ("Sample", "texture"),
+    ("SampleBias", "texture"),
+    ("SampleLevel", "textureLod"),
+    ("SampleGrad", "textureGrad"),
+];
+
+/// Definitions of the HLSL intrinsics used by lifted programs which GLSL doesn't have
+fn write_prelude(w: &mut impl Write) -> std::fmt::Result {
+    for ty in ["float", "vec2", "vec3", "vec4"] {
+        writeln!(w, "{ty} saturate({ty} x) {{ return clamp(x, 0.0, 1.0); }}")?;
+        writeln!(w, "{ty} rcp({ty} x) {{ return 1.0 / x; }}")?;
+    }
+    // Registers are untyped, so values are moved between them bit-for-bit
+    for (float, int, uint) in [
+        ("float", "int", "uint"),
+        ("vec2", "ivec2", "uvec2"),
+        ("vec3", "ivec3", "uvec3"),
+        ("vec4", "ivec4", "uvec4"),
+    ] {
+        writeln!(w, "{float} asfloat({float} x) {{ return x; }}")?;
+        writeln!(w, "{float} asfloat({int} x) {{ return intBitsToFloat(x); }}")?;
+        writeln!(w, "{float} asfloat({uint} x) {{ return uintBitsToFloat(x); }}")?;
+        writeln!(w, "{int} asint({float} x) {{ return floatBitsToInt(x); }}")?;
+        writeln!(w, "{uint} asuint({float} x) {{ return floatBitsToUint(x); }}")?;
+    }
+    writeln!(w)
+}
+
+/// Rewrites a texture method call like `t0.Sample(s0, uv)`, where `rest` follows the texture name, as `texture(t0, uv`.
+/// Returns the rewritten start of the call and the number of bytes of `rest` it replaces.
+fn translate_texture_method(texture: &str, rest: &str) -> Option<(String, usize)> {
+    let method = rest.strip_prefix('.')?;
+    let method_end = method.find(|c: char| !c.is_ascii_alphanumeric())?;
+    let (_, function) = TEXTURE_METHODS.iter().find(|(name, _)| *name == &method[..method_end])?;
+    let arguments = method[method_end..].strip_prefix('(')?;
+    let sampler_end = arguments.find(',')? + 1;
+    let skipped = arguments[sampler_end..].len() - arguments[sampler_end..].trim_start().len();
+    Some((format!("{function}({texture}, "), 1 + method_end + 1 + sampler_end + skipped))
+}
+
+/// Renames HLSL identifiers and texture methods in a statement to their GLSL equivalents,
+/// leaving everything else untouched
+fn translate_statement(statement: &str) -> String {
+    let mut translated = String::with_capacity(statement.len());
+    let mut chars = statement.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c.is_ascii_alphabetic() || c == '_' {
+            let mut end = start + c.len_utf8();
+            while let Some((i, next)) = chars.peek().copied() {
+                if !(next.is_ascii_alphanumeric() || next == '_') {
+                    break;
+                }
+                end = i + next.len_utf8();
+                chars.next();
+            }
+            let word = &statement[start..end];
+            let rest = &statement[end..];
+            if let Some((call, replaced)) = translate_texture_method(word, rest) {
+                translated.push_str(&call);
+                while chars.peek().is_some_and(|(i, _)| *i < end + replaced) {
+                    chars.next();
+                }
+                continue;
+            }
+            match RENAMES.iter().find(|(hlsl, _)| *hlsl == word) {
+                Some((_, glsl)) => translated.push_str(glsl),
+                None => translated.push_str(word),
+            }
+        } else if c.is_ascii_digit() {
+            // Keep numbers like 2e3 or 0x1f together, so their letters aren't taken for identifiers
+            let mut end = start + c.len_utf8();
+            while let Some((i, next)) = chars.peek().copied() {
+                if !(next.is_ascii_alphanumeric() || next == '.' || next == '_') {
+                    break;
+                }
+                end = i + next.len_utf8();
+                chars.next();
+            }
+            translated.push_str(&statement[start..end]);
+        } else {
+            translated.push(c);
+        }
+    }
+    translated
+}
+
+/// The GLSL name of a vector of a scalar D3D_SHADER_VARIABLE_TYPE, e.g. `ivec3`
+fn vector_type_name(base: u16, width: u16) -> String {
+    let (scalar, prefix) = match base {
+        1 => ("bool", "b"),
+        2 => ("int", "i"),
+        19 => ("uint", "u"),
+        39 => ("double", "d"),
+        _ => ("float", ""),
+    };
+    match width {
+        0 | 1 => scalar.to_owned(),
+        width => format!("{prefix}vec{width}"),
+    }
+}
+
+/// The GLSL type of a constant buffer variable, not including any array length
+fn variable_type_name(ty: &VariableType) -> String {
+    match ty.class {
+        // Scalar
+        0 => vector_type_name(ty.base, 1),
+        // Vector
+        1 => vector_type_name(ty.base, ty.columns),
+        // Matrices, which GLSL names by columns then rows
+        2 | 3 => {
+            let prefix = if ty.base == 39 { "d" } else { "" };
+            let layout = if ty.class == 2 { "layout(row_major) " } else { "" };
+            if ty.rows == ty.columns {
+                format!("{layout}{prefix}mat{}", ty.rows)
+            } else {
+                format!("{layout}{prefix}mat{}x{}", ty.columns, ty.rows)
+            }
+        }
+        // Struct
+        5 => {
+            let members: Vec<String> = ty
+                .members
+                .iter()
+                .map(|(name, _, member)| format!("{} {name}{};", variable_type_name(member), array_suffix(member)))
+                .collect();
+            format!("struct {{ {} }}", members.join(" "))
+        }
+        _ => vector_type_name(ty.base, 1),
+    }
+}
+
+fn array_suffix(ty: &VariableType) -> String {
+    if ty.elements > 0 {
+        format!("[{}]", ty.elements)
+    } else {
+        String::new()
+    }
+}
+
+/// The D3D_SHADER_VARIABLE_TYPE of the components of a signature element
+fn element_base_type(element: &SignatureElement) -> u16 {
+    match element.component_type {
+        ComponentType::UInt => 19,
+        ComponentType::SInt => 2,
+        ComponentType::Float | ComponentType::Unknown(_) => 3,
+    }
+}
+
+/// The GLSL type of a signature element, e.g. `vec3`
+fn element_type_name(element: &SignatureElement) -> String {
+    vector_type_name(element_base_type(element), element.width() as u16)
+}
+
+/// The expression giving the register bits of an input which GLSL provides as a built-in variable
+fn builtin_input(stage: ShaderStage, element: &SignatureElement) -> Option<&'static str> {
+    match element.semantic_name.to_ascii_lowercase().as_str() {
+        "sv_position" if stage == ShaderStage::Fragment => Some("gl_FragCoord"),
+        "sv_vertexid" => Some("asfloat(gl_VertexID)"),
+        "sv_instanceid" => Some("asfloat(gl_InstanceID)"),
+        "sv_primitiveid" => Some("asfloat(gl_PrimitiveID)"),
+        "sv_sampleindex" => Some("asfloat(gl_SampleID)"),
+        "sv_isfrontface" => Some("asfloat(gl_FrontFacing ? 0xFFFFFFFFu : 0u)"),
+        _ => None,
+    }
+}
+
+/// The built-in variable an output is written to, if GLSL has one for it
+fn builtin_output(stage: ShaderStage, element: &SignatureElement) -> Option<&'static str> {
+    match element.semantic_name.to_ascii_lowercase().as_str() {
+        "sv_position" if stage != ShaderStage::Fragment => Some("gl_Position"),
+        "sv_depth" => Some("gl_FragDepth"),
+        _ => None,
+    }
+}
+
+/// Picks the location of each user-defined input or output.
+///
+/// Elements use their signature register, but GLSL doesn't allow components of different types at one location,
+/// so elements whose type differs from the first element packed into their register are moved to locations after
+/// the last register. Both sides of a stage boundary share the packing, so they still agree on the locations.
+fn assign_locations(elements: &[&SignatureElement]) -> Vec<u32> {
+    let mut next = elements
+        .iter()
+        .map(|element| element.register.saturating_add(1))
+        .max()
+        .unwrap_or(0);
+    let mut register_types: Vec<(u32, u16)> = vec![];
+    // (register, type, location) of the elements which have been moved
+    let mut moved: Vec<(u32, u16, u32)> = vec![];
+    elements
+        .iter()
+        .map(|element| {
+            let base = element_base_type(element);
+            match register_types.iter().find(|(register, _)| *register == element.register) {
+                None => {
+                    register_types.push((element.register, base));
+                    element.register
+                }
+                Some((_, register_base)) if *register_base == base => element.register,
+                Some(_) => match moved.iter().find(|(register, moved_base, _)| *register == element.register && *moved_base == base) {
+                    Some((_, _, location)) => *location,
+                    None => {
+                        let location = next;
+                        next = next.saturating_add(1);
+                        moved.push((element.register, base, location));
+                        location
+                    }
+                },
+            }
+        })
+        .collect()
+}
+
+/// The layout qualifier for a user-defined input or output at `location`
+fn location_layout(element: &SignatureElement, location: u32) -> String {
+    let component = element.mask.trailing_zeros();
+    if component == 0 {
+        format!("layout(location = {location})")
+    } else {
+        format!("layout(location = {location}, component = {component})")
+    }
+}
+
+/// The GLSL declaration of a resource binding, or None if GLSL has no equivalent
+fn resource_declaration(binding: &ResourceBinding) -> Option<String> {
+    let register = format!("{}{}", binding.kind.register_prefix(), binding.bind_point);
+    if binding.kind == ResourceKind::Sampler {
+        return Some(format!("// {register}: sampler {}, combined into the textures it's used with", binding.name));
+    }
+    if binding.kind != ResourceKind::Texture {
+        return None;
+    }
+    let dimension = match binding.dimension {
+        1 => "samplerBuffer",
+        2 => "sampler1D",
+        3 => "sampler1DArray",
+        4 => "sampler2D",
+        5 => "sampler2DArray",
+        6 => "sampler2DMS",
+        7 => "sampler2DMSArray",
+        8 => "sampler3D",
+        9 => "samplerCube",
+        10 => "samplerCubeArray",
+        _ => return None,
+    };
+    let prefix = match binding.return_type {
+        3 => "i",
+        4 => "u",
+        _ => "",
+    };
+    let array = if binding.bind_count > 1 {
+        format!("[{}]", binding.bind_count)
+    } else {
+        String::new()
+    };
+    Some(format!(
+        "layout(binding = {}) uniform {prefix}{dimension} {register}{array}; // {}",
+        binding.bind_point, binding.name
+    ))
+}
+
+/// Declares the `v#`/`o#` registers used by a signature, once each
+fn write_register_declarations(w: &mut impl Write, prefix: &str, elements: &[SignatureElement]) -> std::fmt::Result {
+    let mut declared = vec![];
+    for element in elements {
+        let register = element.register_name(prefix);
+        if !declared.contains(&register) {
+            writeln!(w, "    vec4 {register} = vec4(0.0);")?;
+            declared.push(register);
+        }
+    }
+    Ok(())
+}
+
+/// Writes the GLSL for a program given as [lifted_statements]
+fn write_glsl_statements(
+    interface: &ShaderInterface,
+    statements: &[String],
+    w: &mut impl Write,
+) -> std::fmt::Result {
+    let stage = interface.stage;
+    writeln!(w, "#version 450")?;
+    writeln!(w, "// {} shader decompiled by yk_fxo_disasm\n", stage.to_str())?;
+    write_prelude(w)?;
+
+    for buffer in &interface.reflection.constant_buffers {
+        let Some(register) = interface.reflection.constant_buffer_register(buffer) else {
+            continue;
+        };
+        writeln!(w, "layout(std140, binding = {register}) uniform {} {{", buffer.name)?;
+        writeln!(w, "    vec4 cb{register}[{}];", buffer.size.div_ceil(16))?;
+        for variable in &buffer.variables {
+            writeln!(
+                w,
+                "    // {}: {} {}{}",
+                variable.location(register),
+                variable_type_name(&variable.ty),
+                variable.name,
+                array_suffix(&variable.ty)
+            )?;
+        }
+        writeln!(w, "}};\n")?;
+    }
+
+    let mut any_resources = false;
+    for binding in &interface.reflection.bindings {
+        if let Some(declaration) = resource_declaration(binding) {
+            writeln!(w, "{declaration}")?;
+            any_resources = true;
+        }
+    }
+    if any_resources {
+        writeln!(w)?;
+    }
+
+    let inputs: Vec<_> = interface.inputs.iter().filter(|element| builtin_input(stage, element).is_none()).collect();
+    for (element, location) in inputs.iter().zip(assign_locations(&inputs)) {
+        // Integer fragment inputs can't be interpolated
+        let flat = if stage == ShaderStage::Fragment && element_base_type(element) != 3 { "flat " } else { "" };
+        writeln!(
+            w,
+            "{} {flat}in {} in_{};",
+            location_layout(element, location),
+            element_type_name(element),
+            element.field_name()
+        )?;
+    }
+    let outputs: Vec<_> = interface.outputs.iter().filter(|element| builtin_output(stage, element).is_none()).collect();
+    for (element, location) in outputs.iter().zip(assign_locations(&outputs)) {
+        writeln!(
+            w,
+            "{} out {} out_{};",
+            location_layout(element, location),
+            element_type_name(element),
+            element.field_name()
+        )?;
+    }
+
+    writeln!(w, "\nvoid main() {{")?;
+    write_register_declarations(w, "v", &interface.inputs)?;
+    for element in &interface.inputs {
+        let value = match builtin_input(stage, element) {
+            // gl_FragCoord is the only vector built-in, the others are scalars and can't be swizzled
+            Some("gl_FragCoord") => format!("gl_FragCoord.{}", element.swizzle()),
+            Some(builtin) => builtin.to_owned(),
+            None => format!("asfloat(in_{})", element.field_name()),
+        };
+        writeln!(w, "    {}.{} = {value};", element.register_name("v"), element.swizzle())?;
+    }
+    write_register_declarations(w, "o", &interface.outputs)?;
+    writeln!(w)?;
+
+    for statement in statements {
+        for line in translate_statement(statement).lines() {
+            writeln!(w, "    {line}")?;
+        }
+    }
+
+    writeln!(w)?;
+    for element in &interface.outputs {
+        let register = format!("{}.{}", element.register_name("o"), element.swizzle());
+        let value = match element.component_type {
+            ComponentType::UInt => format!("asuint({register})"),
+            ComponentType::SInt => format!("asint({register})"),
+            ComponentType::Float | ComponentType::Unknown(_) => register,
+        };
+        match builtin_output(stage, element) {
+            Some(builtin) => writeln!(w, "    {builtin} = {value};")?,
+            None => writeln!(w, "    out_{} = {value};", element.field_name())?,
+        }
+    }
+    writeln!(w, "}}")
+}
+
+/// Writes the GLSL for a program, see the module documentation
+pub fn write_glsl<T: HLSLCompatibleAbstractVM>(
+    interface: &ShaderInterface,
+    program: &impl Program<T>,
+    w: &mut impl Write,
+) -> std::fmt::Result {
+    write_glsl_statements(interface, &lifted_statements(program), w)
+}
+
+/// Returns the GLSL written by [write_glsl]
+pub fn glsl_source<T: HLSLCompatibleAbstractVM>(interface: &ShaderInterface, program: &impl Program<T>) -> String {
+    let mut source = String::new();
+    write_glsl(interface, program, &mut source).unwrap();
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        disasm::disassemble_amdil_text,
+        dxbc::{ConstantBuffer, ConstantVariable, Reflection},
+    };
+
+    /// Set to overwrite the golden files with the current output instead of comparing against them
+    const BLESS_ENV: &str = "YK_FXO_DISASM_BLESS";
+
+    fn element(semantic_name: &str, semantic_index: u32, component_type: ComponentType, register: u32, mask: u8) -> SignatureElement {
+        SignatureElement {
+            semantic_name: semantic_name.to_owned(),
+            semantic_index,
+            system_value: 0,
+            component_type,
+            register,
+            mask,
+        }
+    }
+
+    fn vector(base: u16, rows: u16, columns: u16) -> VariableType {
+        VariableType {
+            class: if rows > 1 { 2 } else { 1 },
+            base,
+            rows,
+            columns,
+            elements: 0,
+            members: vec![],
+        }
+    }
+
+    fn binding(name: &str, kind: ResourceKind, bind_point: u32) -> ResourceBinding {
+        ResourceBinding {
+            name: name.to_owned(),
+            kind,
+            return_type: if kind == ResourceKind::Texture { 5 } else { 0 },
+            dimension: if kind == ResourceKind::Texture { 4 } else { 0 },
+            bind_point,
+            bind_count: 1,
+            components: 4,
+        }
+    }
+
+    /// Decodes an AMDIL program and compares its GLSL against `testdata/glsl/{name}.glsl`
+    fn check_golden(name: &str, interface: &ShaderInterface, amdil: &str) {
+        let program = disassemble_amdil_text(amdil.as_bytes()).unwrap_or_else(|e| panic!("couldn't decode {name}: {e}"));
+        let mut actual = String::new();
+        write_glsl_statements(interface, &lifted_statements(&program), &mut actual).unwrap();
+
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("testdata/glsl/{name}.glsl"));
+        if std::env::var_os(BLESS_ENV).is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, &actual).unwrap();
+            return;
+        }
+        let expected = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("couldn't read {}: {e}, set {BLESS_ENV} to create it", path.display()));
+        assert!(
+            actual == expected,
+            "GLSL for {name} doesn't match {}, set {BLESS_ENV} to update it\n{}",
+            path.display(),
+            similar::TextDiff::from_lines(&expected, &actual).unified_diff()
+        );
+    }
+
+    #[test]
+    fn translates_hlsl_names() {
+        assert_eq!(
+            translate_statement("r0.xy = lerp(float2(1.0f, 2e3), frac(v0.xy), rsqrt(r1.x));"),
+            "r0.xy = mix(vec2(1.0f, 2e3), fract(v0.xy), inversesqrt(r1.x));"
+        );
+        // Identifiers which only contain a renamed name are left alone
+        assert_eq!(translate_statement("lerp_factor = float4_count;"), "lerp_factor = float4_count;");
+        assert_eq!(translate_statement("r0.x = r1.x"), "r0.x = r1.x");
+        assert_eq!(
+            translate_statement("r0 = t1.SampleLevel(s2, v0.xy, 0.0f) + t1.Sample( s0,lerp(v0.xy, v0.zw, 0.5));"),
+            "r0 = textureLod(t1, v0.xy, 0.0f) + texture(t1, mix(v0.xy, v0.zw, 0.5));"
+        );
+    }
+
+    #[test]
+    fn golden_vertex_transform() {
+        let interface = ShaderInterface {
+            stage: ShaderStage::Vertex,
+            inputs: vec![
+                element("POSITION", 0, ComponentType::Float, 0, 0b0111),
+                element("TEXCOORD", 0, ComponentType::Float, 1, 0b0011),
+                element("TEXCOORD", 1, ComponentType::Float, 1, 0b1100),
+                element("SV_VertexID", 0, ComponentType::UInt, 2, 0b0001),
+            ],
+            outputs: vec![
+                element("SV_Position", 0, ComponentType::Float, 0, 0b1111),
+                element("TEXCOORD", 0, ComponentType::Float, 1, 0b0011),
+                element("TEXCOORD", 1, ComponentType::UInt, 1, 0b0100),
+            ],
+            reflection: Reflection {
+                constant_buffers: vec![ConstantBuffer {
+                    name: "Transform".to_owned(),
+                    size: 80,
+                    variables: vec![
+                        ConstantVariable {
+                            name: "g_WorldViewProj".to_owned(),
+                            offset: 0,
+                            size: 64,
+                            ty: vector(3, 4, 4),
+                        },
+                        ConstantVariable {
+                            name: "g_UVScale".to_owned(),
+                            offset: 64,
+                            size: 8,
+                            ty: vector(3, 1, 2),
+                        },
+                    ],
+                }],
+                bindings: vec![binding("Transform", ResourceKind::ConstantBuffer, 1)],
+            },
+        };
+        check_golden(
+            "vertex_transform",
+            &interface,
+            "il_vs_2_0
+dcl_global_flags refactoringAllowed
+dcl_cb cb1[5]
+dcl_literal l0, 0x00000000, 0x00000000, 0x00000000, 0x3F800000
+dcl_input_generic v0
+dcl_input_generic v1
+dcl_input_vertexid v2.x___
+dcl_output_position o0
+dcl_output_generic o1
+mov r0.xyz_, v0.xyz0
+mov r0.___w, l0.000w
+dp4_ieee o0.x___, r0, cb1[0]
+dp4_ieee o0._y__, r0, cb1[1]
+dp4_ieee o0.__z_, r0, cb1[2]
+dp4_ieee o0.___w, r0, cb1[3]
+mov_sat r1.x___, cb1[4].x000
+lrp r1.xy__, r1.xx00, v1.zw00, v1.xy00
+mul_ieee o1.xy__, r1.xy00, cb1[4].xy00
+mov o1.__z_, v2.00x0
+ret_dyn
+end
+",
+        );
+    }
+
+    #[test]
+    fn golden_pixel_textured() {
+        let mut depth = element("SV_Depth", 0, ComponentType::Float, u32::MAX, 0b0001);
+        depth.system_value = 65;
+        let interface = ShaderInterface {
+            stage: ShaderStage::Fragment,
+            inputs: vec![
+                element("SV_Position", 0, ComponentType::Float, 0, 0b1111),
+                element("TEXCOORD", 0, ComponentType::Float, 1, 0b0011),
+                element("TEXCOORD", 1, ComponentType::UInt, 1, 0b0100),
+                element("SV_IsFrontFace", 0, ComponentType::UInt, 2, 0b0001),
+            ],
+            outputs: vec![
+                element("SV_Target", 0, ComponentType::Float, 0, 0b1111),
+                element("SV_Target", 1, ComponentType::UInt, 1, 0b0001),
+                depth,
+            ],
+            reflection: Reflection {
+                constant_buffers: vec![],
+                bindings: vec![
+                    binding("g_LinearWrap", ResourceKind::Sampler, 0),
+                    binding("g_Albedo", ResourceKind::Texture, 3),
+                ],
+            },
+        };
+        check_golden(
+            "pixel_textured",
+            &interface,
+            "il_ps_2_0
+dcl_global_flags refactoringAllowed
+dcl_resource_id(3)_type(2d)_fmtx(float)_fmty(float)_fmtz(float)_fmtw(float)
+dcl_sampler s0
+dcl_literal l0, 0x00000001, 0x00000000, 0x00000000, 0x00000000
+dcl_input_position_interp(linear_noperspective) v0
+dcl_input_generic_interp(linear) v1.xy__
+dcl_input_generic_interp(constant) v1.__z_
+dcl_input_is_front_face v2.x___
+dcl_output_generic o0
+dcl_output_generic o1
+dcl_output_depth oDepth
+sample_resource(3)_sampler(0) r0, v1.xy00
+if_logicalz v2.x
+    rsq r1.x___, v0.w
+    mul_ieee r0.xyz_, r0.xyz0, r1.xxx0
+    frc r0.xyz_, r0.xyz0
+endif
+mov o0, r0
+iadd o1.x___, v1.z000, l0.x000
+mov_sat oDepth.x___, v0.z000
+ret_dyn
+end
+",
+        );
+    }
+}
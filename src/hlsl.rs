@@ -0,0 +1,388 @@
+//! This module renders a decoded shader program back into HLSL source, for reading rather than recompiling.
+//!
+//! The declarations come from the DXBC [ShaderInterface]:
+//! the input/output structs from the signatures, and the constant buffers and resources from the reflection data.
+//! Constant buffers are declared as raw `float4` arrays named after their registers, so the program text can index
+//! them directly, with the reflected variables listed in comments next to them.
+//!
+//! The inputs are copied into `v#` registers before the body, and the outputs copied out of `o#` registers after it.
+//! The body is the program from [lifted_statements].
+
+use std::fmt::Write;
+
+use turnip_gfx_disasm::{hlsl::compat::HLSLCompatibleAbstractVM, Program};
+
+use crate::{
+    disasm::lifted_statements,
+    dxbc::{ComponentType, ResourceBinding, ResourceKind, ShaderInterface, SignatureElement, VariableType},
+};
+
+/// The HLSL name of a scalar D3D_SHADER_VARIABLE_TYPE
+fn scalar_type_name(base: u16) -> &'static str {
+    match base {
+        1 => "bool",
+        2 => "int",
+        19 => "uint",
+        39 => "double",
+        _ => "float",
+    }
+}
+
+/// The HLSL type of a constant buffer variable, not including any array length
+fn variable_type_name(ty: &VariableType) -> String {
+    let scalar = scalar_type_name(ty.base);
+    match ty.class {
+        // Scalar
+        0 => scalar.to_owned(),
+        // Vector
+        1 => format!("{scalar}{}", ty.columns),
+        // Row-major matrix
+        2 => format!("row_major {scalar}{}x{}", ty.rows, ty.columns),
+        // Column-major matrix
+        3 => format!("{scalar}{}x{}", ty.rows, ty.columns),
+        // Struct
+        5 => {
+            let members: Vec<String> = ty
+                .members
+                .iter()
+                .map(|(name, _, member)| format!("{} {name}{};", variable_type_name(member), array_suffix(member)))
+                .collect();
+            format!("struct {{ {} }}", members.join(" "))
+        }
+        _ => scalar.to_owned(),
+    }
+}
+
+fn array_suffix(ty: &VariableType) -> String {
+    if ty.elements > 0 {
+        format!("[{}]", ty.elements)
+    } else {
+        String::new()
+    }
+}
+
+/// The HLSL type of a signature element, e.g. `float3`
+fn element_type_name(element: &SignatureElement) -> String {
+    let scalar = match element.component_type {
+        ComponentType::UInt => "uint",
+        ComponentType::SInt => "int",
+        ComponentType::Float | ComponentType::Unknown(_) => "float",
+    };
+    match element.width() {
+        0 | 1 => scalar.to_owned(),
+        width => format!("{scalar}{width}"),
+    }
+}
+
+/// The HLSL declaration of a resource binding, or None if it isn't a texture or sampler
+fn resource_declaration(binding: &ResourceBinding) -> Option<String> {
+    let register = format!("{}{}", binding.kind.register_prefix(), binding.bind_point);
+    let array = if binding.bind_count > 1 {
+        format!("[{}]", binding.bind_count)
+    } else {
+        String::new()
+    };
+    let ty = match binding.kind {
+        ResourceKind::Sampler => "SamplerState".to_owned(),
+        ResourceKind::Texture | ResourceKind::UnorderedAccess => {
+            let dimension = match binding.dimension {
+                1 => "Buffer",
+                2 => "Texture1D",
+                3 => "Texture1DArray",
+                4 => "Texture2D",
+                5 => "Texture2DArray",
+                6 => "Texture2DMS",
+                7 => "Texture2DMSArray",
+                8 => "Texture3D",
+                9 => "TextureCube",
+                10 => "TextureCubeArray",
+                _ => return None,
+            };
+            let scalar = match binding.return_type {
+                3 => "int",
+                4 => "uint",
+                7 => "double",
+                _ => "float",
+            };
+            let element = match binding.components {
+                1 => scalar.to_owned(),
+                components => format!("{scalar}{components}"),
+            };
+            let rw = if binding.kind == ResourceKind::UnorderedAccess { "RW" } else { "" };
+            format!("{rw}{dimension}<{element}>")
+        }
+        _ => return None,
+    };
+    Some(format!("{ty} {register}{array} : register({register}); // {}", binding.name))
+}
+
+fn write_struct(w: &mut impl Write, name: &str, elements: &[SignatureElement]) -> std::fmt::Result {
+    writeln!(w, "struct {name} {{")?;
+    for element in elements {
+        writeln!(
+            w,
+            "    {} {} : {}{};",
+            element_type_name(element),
+            element.field_name(),
+            element.semantic_name,
+            element.semantic_index
+        )?;
+    }
+    writeln!(w, "}};\n")
+}
+
+/// Declares the `v#`/`o#` registers used by a signature, once each
+fn write_register_declarations(w: &mut impl Write, prefix: &str, elements: &[SignatureElement]) -> std::fmt::Result {
+    let mut declared = vec![];
+    for element in elements {
+        let register = element.register_name(prefix);
+        if !declared.contains(&register) {
+            writeln!(w, "    float4 {register} = 0;")?;
+            declared.push(register);
+        }
+    }
+    Ok(())
+}
+
+/// Reads a signature element's components from its register, reinterpreting the float bits if it isn't float
+fn read_register(prefix: &str, element: &SignatureElement) -> String {
+    let register = format!("{}.{}", element.register_name(prefix), element.swizzle());
+    match element.component_type {
+        ComponentType::UInt => format!("asuint({register})"),
+        ComponentType::SInt => format!("asint({register})"),
+        ComponentType::Float | ComponentType::Unknown(_) => register,
+    }
+}
+
+/// Writes the HLSL for a program given as [lifted_statements]
+fn write_hlsl_statements(
+    interface: &ShaderInterface,
+    statements: &[String],
+    w: &mut impl Write,
+) -> std::fmt::Result {
+    let prefix = interface.stage.short_name().to_uppercase();
+
+    writeln!(w, "// {} shader decompiled by yk_fxo_disasm\n", interface.stage.to_str())?;
+
+    for buffer in &interface.reflection.constant_buffers {
+        let Some(register) = interface.reflection.constant_buffer_register(buffer) else {
+            continue;
+        };
+        writeln!(w, "cbuffer {} : register(b{register}) {{", buffer.name)?;
+        writeln!(w, "    float4 cb{register}[{}];", buffer.size.div_ceil(16))?;
+        for variable in &buffer.variables {
+            writeln!(
+                w,
+                "    // {}: {} {}{}",
+                variable.location(register),
+                variable_type_name(&variable.ty),
+                variable.name,
+                array_suffix(&variable.ty)
+            )?;
+        }
+        writeln!(w, "}};\n")?;
+    }
+
+    let mut any_resources = false;
+    for binding in &interface.reflection.bindings {
+        if let Some(declaration) = resource_declaration(binding) {
+            writeln!(w, "{declaration}")?;
+            any_resources = true;
+        }
+    }
+    if any_resources {
+        writeln!(w)?;
+    }
+
+    write_struct(w, &format!("{prefix}_INPUT"), &interface.inputs)?;
+    write_struct(w, &format!("{prefix}_OUTPUT"), &interface.outputs)?;
+
+    writeln!(w, "void main(in {prefix}_INPUT input, out {prefix}_OUTPUT output) {{")?;
+    write_register_declarations(w, "v", &interface.inputs)?;
+    for element in &interface.inputs {
+        writeln!(
+            w,
+            "    {}.{} = asfloat(input.{});",
+            element.register_name("v"),
+            element.swizzle(),
+            element.field_name()
+        )?;
+    }
+    write_register_declarations(w, "o", &interface.outputs)?;
+    writeln!(w)?;
+
+    for statement in statements {
+        for line in statement.lines() {
+            writeln!(w, "    {line}")?;
+        }
+    }
+
+    writeln!(w)?;
+    for element in &interface.outputs {
+        writeln!(w, "    output.{} = {};", element.field_name(), read_register("o", element))?;
+    }
+    writeln!(w, "}}")
+}
+
+/// Writes the HLSL for a program, see the module documentation
+pub fn write_hlsl<T: HLSLCompatibleAbstractVM>(
+    interface: &ShaderInterface,
+    program: &impl Program<T>,
+    w: &mut impl Write,
+) -> std::fmt::Result {
+    write_hlsl_statements(interface, &lifted_statements(program), w)
+}
+
+/// Returns the HLSL written by [write_hlsl]
+pub fn hlsl_source<T: HLSLCompatibleAbstractVM>(interface: &ShaderInterface, program: &impl Program<T>) -> String {
+    let mut source = String::new();
+    write_hlsl(interface, program, &mut source).unwrap();
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db::ShaderStage, disasm::disassemble_amdil_text};
+
+    /// Set to overwrite the golden files with the current output instead of comparing against them
+    const BLESS_ENV: &str = "YK_FXO_DISASM_BLESS";
+
+    fn words(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_le_bytes()).collect()
+    }
+
+    /// Appends `bytes` to a chunk and returns their offset in it
+    fn append(chunk: &mut Vec<u8>, bytes: &[u8]) -> u32 {
+        let offset = chunk.len() as u32;
+        chunk.extend_from_slice(bytes);
+        offset
+    }
+
+    /// An `ISGN`/`OSGN` chunk from (semantic name, semantic index, system value, component type, register, mask)
+    fn signature(elements: &[(&str, u32, u32, u32, u32, u8)]) -> Vec<u8> {
+        let mut chunk = words(&[elements.len() as u32, 8]);
+        let mut strings = vec![];
+        for &(name, index, system_value, component_type, register, mask) in elements {
+            let name_offset = (8 + 24 * elements.len() + strings.len()) as u32;
+            strings.extend_from_slice(name.as_bytes());
+            strings.push(0);
+            chunk.extend(words(&[name_offset, index, system_value, component_type, register]));
+            chunk.extend([mask, mask, 0, 0]);
+        }
+        chunk.extend(strings);
+        chunk
+    }
+
+    /// A shader model 5 `RDEF` chunk for a pixel shader, with a `Material` constant buffer bound to `b1`,
+    /// a texture bound to `t2` and a sampler bound to `s0`
+    fn material_rdef() -> Vec<u8> {
+        let mut rdef = vec![0; 28];
+        let name = |rdef: &mut Vec<u8>, name: &str| append(rdef, format!("{name}\0").as_bytes());
+
+        // class, base type, rows, columns, elements, members, member offset
+        let float3 = append(&mut rdef, &[words(&[0x0003_0001, 0x0003_0001]), words(&[0, 0])].concat());
+        let float1 = append(&mut rdef, &[words(&[0x0003_0000, 0x0001_0001]), words(&[0, 0])].concat());
+        let tint = name(&mut rdef, "g_Tint");
+        let alpha = name(&mut rdef, "g_Alpha");
+        let material = name(&mut rdef, "Material");
+        let sampler = name(&mut rdef, "g_LinearWrap");
+        let texture = name(&mut rdef, "g_Albedo");
+
+        // name, offset, size, flags, type, default value, texture start/size, sampler start/size
+        let variables = append(&mut rdef, &words(&[tint, 0, 12, 2, float3, 0, u32::MAX, 0, u32::MAX, 0]));
+        append(&mut rdef, &words(&[alpha, 12, 4, 2, float1, 0, u32::MAX, 0, u32::MAX, 0]));
+        // name, variable count, variable offset, size, flags, type
+        let buffers = append(&mut rdef, &words(&[material, 2, variables, 16, 0, 0]));
+        // name, input type, return type, dimension, samples, bind point, bind count, flags
+        let bindings = append(&mut rdef, &words(&[sampler, 3, 0, 0, 0, 0, 1, 0]));
+        append(&mut rdef, &words(&[texture, 2, 5, 4, u32::MAX, 2, 1, 0xc]));
+        append(&mut rdef, &words(&[material, 0, 0, 0, 0, 1, 1, 1]));
+
+        rdef[..28].copy_from_slice(&words(&[1, buffers, 3, bindings, 0xffff_0500, 0, 0]));
+        rdef
+    }
+
+    /// Wraps chunks in a DXBC container
+    fn dxbc(chunks: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let header_len = 32 + 4 * chunks.len();
+        let mut offsets = vec![];
+        let mut data = vec![];
+        for (fourcc, chunk) in chunks {
+            offsets.push((header_len + data.len()) as u32);
+            data.extend_from_slice(*fourcc);
+            data.extend(words(&[chunk.len() as u32]));
+            data.extend(chunk);
+        }
+        let mut dxbc = b"DXBC".to_vec();
+        dxbc.extend([0; 16]);
+        dxbc.extend(words(&[1, (header_len + data.len()) as u32, chunks.len() as u32]));
+        dxbc.extend(words(&offsets));
+        dxbc.extend(data);
+        dxbc
+    }
+
+    /// Decodes an AMDIL program and compares its HLSL against `testdata/hlsl/{name}.hlsl`
+    fn check_golden(name: &str, interface: &ShaderInterface, amdil: &str) {
+        let program = disassemble_amdil_text(amdil.as_bytes()).unwrap_or_else(|e| panic!("couldn't decode {name}: {e}"));
+        let mut actual = String::new();
+        write_hlsl_statements(interface, &lifted_statements(&program), &mut actual).unwrap();
+
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("testdata/hlsl/{name}.hlsl"));
+        if std::env::var_os(BLESS_ENV).is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, &actual).unwrap();
+            return;
+        }
+        let expected = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("couldn't read {}: {e}, set {BLESS_ENV} to create it", path.display()));
+        assert!(
+            actual == expected,
+            "HLSL for {name} doesn't match {}, set {BLESS_ENV} to update it\n{}",
+            path.display(),
+            similar::TextDiff::from_lines(&expected, &actual).unified_diff()
+        );
+    }
+
+    #[test]
+    fn golden_pixel_material() {
+        let dxbc = dxbc(&[
+            (
+                b"ISGN",
+                signature(&[
+                    ("SV_Position", 0, 1, 3, 0, 0b1111),
+                    ("TEXCOORD", 0, 0, 3, 1, 0b0011),
+                    ("SV_IsFrontFace", 0, 9, 1, 2, 0b0001),
+                ]),
+            ),
+            (b"OSGN", signature(&[("SV_Target", 0, 0, 3, 0, 0b1111)])),
+            (b"RDEF", material_rdef()),
+            (b"SHEX", words(&[0x0000_0050])),
+        ]);
+        let interface = ShaderInterface::from_dxbc(&dxbc, ShaderStage::Fragment);
+        assert_eq!(interface.inputs.len(), 3);
+        assert_eq!(interface.reflection.constant_buffers[0].variables.len(), 2);
+        assert_eq!(interface.reflection.bindings.len(), 3);
+
+        check_golden(
+            "pixel_material",
+            &interface,
+            "il_ps_2_0
+dcl_global_flags refactoringAllowed
+dcl_cb cb1[1]
+dcl_resource_id(2)_type(2d)_fmtx(float)_fmty(float)_fmtz(float)_fmtw(float)
+dcl_sampler s0
+dcl_literal l0, 0x00000000, 0x00000000, 0x00000000, 0x00000000
+dcl_input_position_interp(linear_noperspective) v0
+dcl_input_generic_interp(linear) v1.xy__
+dcl_input_is_front_face v2.x___
+dcl_output_generic o0
+sample_resource(2)_sampler(0) r0, v1.xy00
+mul_ieee o0.xyz_, r0.xyz0, cb1[0].xyz0
+cmov_logical o0.___w, v2.x, cb1[0].w, l0.x
+ret_dyn
+end
+",
+        );
+    }
+}
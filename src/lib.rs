@@ -1,4 +1,13 @@
 pub mod compile;
 pub mod disasm;
 pub mod yk;
-pub mod db;
\ No newline at end of file
+pub mod db;
+pub mod dxbc;
+pub mod par;
+pub mod sllz;
+pub mod source;
+pub mod worker;
+pub mod report;
+pub mod analysis;
+pub mod hlsl;
+pub mod glsl;
\ No newline at end of file
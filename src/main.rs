@@ -1,18 +1,15 @@
-use std::path::PathBuf;
-
-mod compile;
-mod disasm;
-mod yk;
-mod db;
+use std::path::{Path, PathBuf};
 
 use amd_dx_gsa::Atidxx64;
 use clap::Parser;
-use disasm::print_output_depedencies;
-use yk::parse_gsfx;
-
-use crate::{
-    compile::compile_dxbc_to_amdil_text,
-    disasm::disassemble_amdil_text,
+use yk_fxo_disasm::{
+    analysis::{analyze_fxo, link_vertex_to_pixel},
+    compile::Compiler,
+    db::{AsicTarget, ShaderStage},
+    disasm::{output_scalar_names, outputs_depending_on, slice_program},
+    glsl::glsl_source,
+    hlsl::hlsl_source,
+    source::{group_shader_files, read_shader_files, ShaderUnit},
 };
 
 /// Simple program to greet a person
@@ -23,49 +20,138 @@ struct Args {
     #[clap(long, value_parser, default_value = "assets/atidxx64.dll")]
     dll_path: PathBuf,
 
-    /// Shader path
+    /// Shader path (.fxo, .vso or .pso), or a directory or .par archive containing shaders.
+    /// Standalone .vso and .pso files with the same name are analysed together.
     #[clap(value_parser)]
     fxo_path: PathBuf,
+
+    /// The GPU architecture to compile for
+    #[clap(long, value_enum, default_value_t = AsicTarget::RDNA2)]
+    target: AsicTarget,
+
+    /// Directory to cache compiler output in, so unchanged shaders aren't recompiled on later runs
+    #[clap(long, value_parser)]
+    cache_dir: Option<PathBuf>,
+
+    /// Write decompiled HLSL for each stage next to the shader file, e.g. `name.vs.hlsl` and `name.ps.hlsl`
+    #[clap(long, action)]
+    emit_hlsl: bool,
+
+    /// Write decompiled GLSL 4.50 for each stage next to the shader file, e.g. `name.vs.glsl` and `name.ps.glsl`
+    #[clap(long, action)]
+    emit_glsl: bool,
+
+    /// Instead of the dependency report, print only the actions which feed this output component, e.g. `o0.w`
+    #[clap(long, value_parser)]
+    slice: Option<String>,
+
+    /// List the outputs which depend on this input: a scalar (`v2.z`), a register (`v2`, `t3`),
+    /// or the name of a constant buffer variable, constant buffer or resource
+    #[clap(long, value_parser)]
+    affected_by: Option<String>,
 }
 
 fn main() {
     let args = Args::parse();
 
-    let dll = unsafe { Atidxx64::try_load_lib_from(args.dll_path).expect("no library found") };
-
-    let fxo = std::fs::read(args.fxo_path).expect("couldn't read fxo file");
-
-    let (_, (gsvs, gsps)) = parse_gsfx(&fxo).expect("couldn't parse fxo file");
-
-    // if false {
-    //     println!("Vertex Program");
-    //     let vert_program = compile_dxbc_to_rdna2(&dll, gsvs.dxbc, disassemble_rdna2)
-    //         .expect("couldn't compile vertex shader")
-    //         .expect("couldn't disassemble vertex shader");
-    //     print_output_depedencies(&vert_program);
-
-    //     println!("\n\nFragment Program");
-    //     let frag_program = compile_dxbc_to_rdna2(&dll, gsps.dxbc, disassemble_rdna2)
-    //         .expect("couldn't compile frag shader")
-    //         .expect("couldn't disassemble frag shader");
-    //     print_output_depedencies(&frag_program);
-    // } else {
-        println!("Vertex Program");
-        let vert_program = compile_dxbc_to_amdil_text(&dll, gsvs.dxbc, |amdil_text| {
-            println!("{}", std::str::from_utf8(&amdil_text).unwrap());
-            disassemble_amdil_text(amdil_text)
-        })
-            .expect("couldn't compile vertex shader")
-            .expect("couldn't disassemble vertex shader");
-        print_output_depedencies(&vert_program);
-
-        println!("\n\nFragment Program");
-        let frag_program = compile_dxbc_to_amdil_text(&dll, gsps.dxbc, |amdil_text| {
-            println!("{}", std::str::from_utf8(&amdil_text).unwrap());
-            disassemble_amdil_text(amdil_text)
-        })
-            .expect("couldn't compile frag shader")
-            .expect("couldn't disassemble frag shader");
-        print_output_depedencies(&frag_program);
-    // }
+    let dll = unsafe { Atidxx64::try_load_lib_from(&args.dll_path).expect("no library found") };
+    let mut compiler = Compiler::new(dll, &args.dll_path).expect("couldn't fingerprint library");
+    if let Some(cache_dir) = &args.cache_dir {
+        compiler = compiler.with_cache_dir(cache_dir).expect("couldn't create cache directory");
+    }
+
+    let files = read_shader_files(&args.fxo_path, |_| true).expect("couldn't read shader file");
+    for failure in &files.failures {
+        println!("ERROR: {}: {}", failure.path, failure.error);
+    }
+
+    for unit in group_shader_files(files.files) {
+        println!("{}", unit.name());
+        print_unit_analysis(&compiler, &unit, &args);
+    }
+
+    if let Some(stats) = compiler.cache_stats() {
+        eprintln!("Compile cache: {} hits, {} misses", stats.hits, stats.misses);
+    }
+}
+
+fn print_unit_analysis(compiler: &Compiler, unit: &ShaderUnit, args: &Args) {
+    let mut interfaces = vec![];
+    for file in unit.files() {
+        let analysis = analyze_fxo(&file.data, compiler, args.target);
+        interfaces.extend(analysis.stages.iter().map(|stage| stage.interface.clone()));
+        if let Some(error) = &analysis.container_error {
+            println!("ERROR: {}: {error}", file.path);
+            continue;
+        }
+        for warning in &analysis.warnings {
+            println!("WARNING: {warning}");
+        }
+
+        for stage in &analysis.stages {
+            println!("\n\n{} Program", stage.stage.to_str());
+            if let Some(amdil) = &stage.amdil {
+                println!("{amdil}");
+            }
+            match (&args.slice, &stage.program) {
+                (Some(output), Some(program)) => match slice_program(program, output) {
+                    Some(actions) => {
+                        println!("Slice of {output}:");
+                        for action in actions {
+                            println!("{action}");
+                        }
+                    }
+                    None => println!(
+                        "{} shader doesn't write {output}, it writes {}",
+                        stage.stage.to_str(),
+                        output_scalar_names(program).join(", ")
+                    ),
+                },
+                _ => {
+                    if let Some(dependencies) = &stage.dependencies {
+                        print!("{dependencies}");
+                    }
+                }
+            }
+            if let (Some(input), Some(program)) = (&args.affected_by, &stage.program) {
+                let outputs = outputs_depending_on(program, &stage.interface.resolve_input_name(input));
+                println!("Outputs depending on {input}: {}", outputs.join(", "));
+            }
+            if let Some(error) = &stage.error {
+                println!("ERROR: {} shader {error}", stage.stage.to_str());
+            }
+            if let Some(program) = &stage.program {
+                if args.emit_hlsl {
+                    write_source(&file.path, stage.stage, "hlsl", &hlsl_source(&stage.interface, program));
+                }
+                if args.emit_glsl {
+                    write_source(&file.path, stage.stage, "glsl", &glsl_source(&stage.interface, program));
+                }
+            }
+        }
+    }
+
+    if let ShaderUnit::Pair { .. } = unit {
+        let find = |stage| interfaces.iter().find(|interface| interface.stage == stage);
+        if let (Some(vs), Some(ps)) = (find(ShaderStage::Vertex), find(ShaderStage::Fragment)) {
+            println!("\n\nVertex to pixel shader linkage");
+            let problems = link_vertex_to_pixel(vs, ps);
+            if problems.is_empty() {
+                println!("Every pixel shader input is written by the vertex shader");
+            }
+            for problem in problems {
+                println!("WARNING: {problem}");
+            }
+        }
+    }
+}
+
+/// Writes decompiled source next to a shader file, e.g. `name.vs.hlsl` for `name.fxo`.
+/// Files inside .par archives don't have a folder to write to, so this fails for them.
+fn write_source(shader_path: &str, stage: ShaderStage, extension: &str, source: &str) {
+    let path = Path::new(shader_path).with_extension(format!("{}.{extension}", stage.short_name()));
+    match std::fs::write(&path, source) {
+        Ok(()) => eprintln!("Wrote {}", path.display()),
+        Err(e) => eprintln!("couldn't write {}: {e}", path.display()),
+    }
 }
@@ -0,0 +1,423 @@
+//! This module reads the `.par` archives used by the Dragon Engine to package game files, including `.fxo` shader containers.
+//!
+//! All integers in a PAR archive use the endianness given in the header.
+//!
+//! # Layout
+//! - A 0x20 byte header, starting with the magic "PARC"
+//! - 64 byte null-padded names for every folder, then every file
+//! - A table of 32 byte folder entries at `folder_info_offset`, each pointing to a contiguous range of subfolders and files
+//! - A table of 32 byte file entries at `file_info_offset`, each pointing to the (possibly SLLZ-compressed) file data
+//!
+//! Folder 0 is the root of the archive.
+
+use std::borrow::Cow;
+
+use nom::{
+    bytes::complete::{tag, take},
+    number::{
+        complete::{u32, u64, u8},
+        Endianness,
+    },
+    sequence::tuple,
+    IResult,
+};
+
+use crate::{sllz::SllzError, yk::YkGfxError};
+
+const NAME_LEN: usize = 64;
+const COMPRESSED_FLAG: u32 = 0x8000_0000;
+
+#[derive(Debug)]
+pub enum ParError {
+    /// The header or one of the tables couldn't be parsed
+    Parse(String),
+    /// A file's data lies outside of the archive
+    FileOutOfBounds { path: String },
+    /// A folder refers to subfolders or files which don't exist
+    FolderOutOfBounds { name: String },
+    /// A compressed file couldn't be decompressed
+    Decompression { path: String, error: SllzError },
+    /// A file's contents aren't the size given in the file table
+    SizeMismatch { path: String, expected: u32, actual: usize },
+}
+impl std::fmt::Display for ParError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParError::Parse(e) => write!(f, "failed to parse PAR archive: {e}"),
+            ParError::FileOutOfBounds { path } => {
+                write!(f, "PAR file '{path}' points outside of the archive")
+            }
+            ParError::FolderOutOfBounds { name } => {
+                write!(f, "PAR folder '{name}' refers to nonexistent entries")
+            }
+            ParError::Decompression { path, error } => {
+                write!(f, "can't decompress PAR file '{path}': {error}")
+            }
+            ParError::SizeMismatch { path, expected, actual } => {
+                write!(f, "PAR file '{path}' is {actual} bytes, but the archive says it's {expected}")
+            }
+        }
+    }
+}
+impl std::error::Error for ParError {}
+
+struct ParHeader {
+    endianness: Endianness,
+    folder_count: usize,
+    folder_info_offset: usize,
+    file_count: usize,
+    file_info_offset: usize,
+}
+
+struct ParFolderInfo {
+    subfolder_count: usize,
+    first_subfolder: usize,
+    file_count: usize,
+    first_file: usize,
+}
+
+struct ParFileInfo {
+    compression_flags: u32,
+    size: u32,
+    compressed_size: u32,
+    offset: u64,
+}
+
+/// A file inside a [ParArchive]
+pub struct ParFile<'a> {
+    /// The path of the file inside the archive, using '/' as a separator
+    pub path: String,
+    /// The size of the file after decompression
+    pub size: u32,
+    pub compressed: bool,
+    /// The raw data of the file, which is SLLZ-compressed if `compressed` is set
+    pub raw_data: &'a [u8],
+}
+impl<'a> ParFile<'a> {
+    /// The file name without any folders
+    pub fn name(&self) -> &str {
+        self.path.rsplit_once('/').map_or(&self.path, |(_, name)| name)
+    }
+
+    /// Returns the contents of the file, decompressing them if necessary, and checks they're the expected size
+    pub fn contents(&self) -> Result<Cow<'a, [u8]>, ParError> {
+        let contents = if self.compressed {
+            crate::sllz::decompress(self.raw_data)
+                .map(Cow::Owned)
+                .map_err(|error| ParError::Decompression {
+                    path: self.path.clone(),
+                    error,
+                })?
+        } else {
+            Cow::Borrowed(self.raw_data)
+        };
+        if contents.len() != self.size as usize {
+            return Err(ParError::SizeMismatch {
+                path: self.path.clone(),
+                expected: self.size,
+                actual: contents.len(),
+            });
+        }
+        Ok(contents)
+    }
+}
+
+/// The files inside a `.par` archive, borrowing their data from the archive bytes
+pub struct ParArchive<'a> {
+    pub files: Vec<ParFile<'a>>,
+}
+impl<'a> ParArchive<'a> {
+    /// Returns true if the bytes start with the PAR magic
+    pub fn is_par(bytes: &[u8]) -> bool {
+        bytes.starts_with(b"PARC")
+    }
+
+    pub fn parse(overall: &'a [u8]) -> Result<Self, ParError> {
+        let (_, header) = parse_par_header(overall).map_err(|e| ParError::Parse(format!("header: {e:?}")))?;
+
+        let names_len = (header.folder_count + header.file_count) * NAME_LEN;
+        let names = overall
+            .get(0x20..(0x20 + names_len))
+            .ok_or_else(|| ParError::Parse("name table is truncated".to_owned()))?;
+        let name_at = |i: usize| {
+            let name = &names[(i * NAME_LEN)..((i + 1) * NAME_LEN)];
+            let len = name.iter().position(|b| *b == 0).unwrap_or(NAME_LEN);
+            String::from_utf8_lossy(&name[..len]).into_owned()
+        };
+
+        let folder_infos = overall
+            .get(header.folder_info_offset..)
+            .ok_or_else(|| ParError::Parse("folder table is out of bounds".to_owned()))?;
+        let (_, folder_infos) = nom::multi::count(
+            |i| parse_par_folder_info(i, header.endianness),
+            header.folder_count,
+        )(folder_infos)
+        .map_err(|e| ParError::Parse(format!("folder table: {e:?}")))?;
+
+        let file_infos = overall
+            .get(header.file_info_offset..)
+            .ok_or_else(|| ParError::Parse("file table is out of bounds".to_owned()))?;
+        let (_, file_infos) = nom::multi::count(
+            |i| parse_par_file_info(i, header.endianness),
+            header.file_count,
+        )(file_infos)
+        .map_err(|e| ParError::Parse(format!("file table: {e:?}")))?;
+
+        // Walk the folder tree from the root to find the full path of each file
+        let mut file_paths: Vec<Option<String>> = vec![None; header.file_count];
+        let mut to_visit = vec![];
+        if header.folder_count > 0 {
+            to_visit.push((0, String::new()));
+        }
+        while let Some((folder_idx, folder_path)) = to_visit.pop() {
+            let folder = &folder_infos[folder_idx];
+            let folder_name = name_at(folder_idx);
+            if folder.first_subfolder + folder.subfolder_count > header.folder_count
+                || folder.first_file + folder.file_count > header.file_count
+            {
+                return Err(ParError::FolderOutOfBounds { name: folder_name });
+            }
+            for subfolder_idx in folder.first_subfolder..(folder.first_subfolder + folder.subfolder_count) {
+                // Guard against malformed archives that loop back on themselves
+                if subfolder_idx <= folder_idx {
+                    return Err(ParError::FolderOutOfBounds { name: folder_name });
+                }
+                to_visit.push((subfolder_idx, format!("{folder_path}{}/", name_at(subfolder_idx))));
+            }
+            let folder_files = folder.first_file..(folder.first_file + folder.file_count);
+            for (file_idx, file_path) in folder_files.clone().zip(&mut file_paths[folder_files]) {
+                *file_path = Some(format!(
+                    "{folder_path}{}",
+                    name_at(header.folder_count + file_idx)
+                ));
+            }
+        }
+
+        let files = file_infos
+            .into_iter()
+            .zip(file_paths)
+            .enumerate()
+            .map(|(i, (info, path))| {
+                let path = path.unwrap_or_else(|| name_at(header.folder_count + i));
+                let raw_data = usize::try_from(info.offset)
+                    .ok()
+                    .and_then(|start| Some(start..start.checked_add(info.compressed_size as usize)?))
+                    .and_then(|range| overall.get(range))
+                    .ok_or_else(|| ParError::FileOutOfBounds { path: path.clone() })?;
+                Ok(ParFile {
+                    path,
+                    size: info.size,
+                    compressed: (info.compression_flags & COMPRESSED_FLAG) != 0,
+                    raw_data,
+                })
+            })
+            .collect::<Result<Vec<_>, ParError>>()?;
+
+        Ok(ParArchive { files })
+    }
+}
+
+fn parse_par_header<'a>(overall: &'a [u8]) -> IResult<&'a [u8], ParHeader, YkGfxError<&'a [u8]>> {
+    let (input, (_magic, _platform, endianness, _size_extended, _relocated)) =
+        tuple((tag(b"PARC"), u8, u8, u8, u8))(overall)?;
+    let endianness = if endianness == 0 {
+        Endianness::Little
+    } else {
+        Endianness::Big
+    };
+
+    let (input, (_version, _data_size)) = tuple((u32(endianness), u32(endianness)))(input)?;
+    let (input, (folder_count, folder_info_offset, file_count, file_info_offset)) = tuple((
+        u32(endianness),
+        u32(endianness),
+        u32(endianness),
+        u32(endianness),
+    ))(input)?;
+
+    Ok((
+        input,
+        ParHeader {
+            endianness,
+            folder_count: folder_count as usize,
+            folder_info_offset: folder_info_offset as usize,
+            file_count: file_count as usize,
+            file_info_offset: file_info_offset as usize,
+        },
+    ))
+}
+
+fn parse_par_folder_info<'a>(
+    input: &'a [u8],
+    endianness: Endianness,
+) -> IResult<&'a [u8], ParFolderInfo, YkGfxError<&'a [u8]>> {
+    let (input, (subfolder_count, first_subfolder, file_count, first_file)) = tuple((
+        u32(endianness),
+        u32(endianness),
+        u32(endianness),
+        u32(endianness),
+    ))(input)?;
+    // attributes and three unknown fields
+    let (input, _) = take(16_usize)(input)?;
+
+    Ok((
+        input,
+        ParFolderInfo {
+            subfolder_count: subfolder_count as usize,
+            first_subfolder: first_subfolder as usize,
+            file_count: file_count as usize,
+            first_file: first_file as usize,
+        },
+    ))
+}
+
+fn parse_par_file_info<'a>(
+    input: &'a [u8],
+    endianness: Endianness,
+) -> IResult<&'a [u8], ParFileInfo, YkGfxError<&'a [u8]>> {
+    let (input, (compression_flags, size, compressed_size, base_offset)) = tuple((
+        u32(endianness),
+        u32(endianness),
+        u32(endianness),
+        u32(endianness),
+    ))(input)?;
+    let (input, (_attributes, extended_offset, _timestamp)) =
+        tuple((u32(endianness), u32(endianness), u64(endianness)))(input)?;
+
+    Ok((
+        input,
+        ParFileInfo {
+            compression_flags,
+            size,
+            compressed_size,
+            offset: ((extended_offset as u64) << 32) | (base_offset as u64),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sllz::{compress, SllzVersion};
+
+    const HEADER_LEN: usize = 0x20;
+    const ENTRY_LEN: usize = 32;
+
+    fn words(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_le_bytes()).collect()
+    }
+
+    /// Builds a little-endian archive.
+    /// Folders are (name, first subfolder, subfolder count, first file, file count) and files are (name, contents, compressed).
+    fn archive(folders: &[(&str, u32, u32, u32, u32)], files: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let folder_info_offset = HEADER_LEN + NAME_LEN * (folders.len() + files.len());
+        let file_info_offset = folder_info_offset + ENTRY_LEN * folders.len();
+        let data_offset = file_info_offset + ENTRY_LEN * files.len();
+
+        let mut par = b"PARC\x02\0\0\x01".to_vec();
+        par.extend(words(&[
+            0x0002_0001,
+            0,
+            folders.len() as u32,
+            folder_info_offset as u32,
+            files.len() as u32,
+            file_info_offset as u32,
+        ]));
+        for name in folders.iter().map(|folder| folder.0).chain(files.iter().map(|file| file.0)) {
+            let mut padded = name.as_bytes().to_vec();
+            padded.resize(NAME_LEN, 0);
+            par.extend(padded);
+        }
+        for &(_, first_subfolder, subfolder_count, first_file, file_count) in folders {
+            par.extend(words(&[subfolder_count, first_subfolder, file_count, first_file, 0, 0, 0, 0]));
+        }
+        let mut data = vec![];
+        for &(_, contents, compressed) in files {
+            let stored = if compressed {
+                compress(contents, SllzVersion::V1)
+            } else {
+                contents.to_vec()
+            };
+            let flags = if compressed { COMPRESSED_FLAG } else { 0 };
+            let offset = (data_offset + data.len()) as u32;
+            par.extend(words(&[flags, contents.len() as u32, stored.len() as u32, offset, 0, 0, 0, 0]));
+            data.extend(stored);
+        }
+        par.extend(data);
+        par
+    }
+
+    fn nested_archive() -> Vec<u8> {
+        archive(
+            &[(".", 1, 1, 0, 1), ("shaders", 2, 1, 1, 1), ("legacy", 0, 0, 2, 1)],
+            &[
+                ("readme.txt", b"hello", false),
+                ("a.fxo", b"GSFX GSFX GSFX GSFX", true),
+                ("b.vso", b"GSVS", false),
+            ],
+        )
+    }
+
+    #[test]
+    fn reads_nested_folders() {
+        let par = nested_archive();
+        let archive = ParArchive::parse(&par).unwrap();
+
+        let files: Vec<_> = archive
+            .files
+            .iter()
+            .map(|file| (file.path.as_str(), file.compressed, file.contents().unwrap().into_owned()))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("readme.txt", false, b"hello".to_vec()),
+                ("shaders/a.fxo", true, b"GSFX GSFX GSFX GSFX".to_vec()),
+                ("shaders/legacy/b.vso", false, b"GSVS".to_vec()),
+            ]
+        );
+        assert_eq!(archive.files[1].name(), "a.fxo");
+    }
+
+    #[test]
+    fn file_out_of_bounds() {
+        let file_info = |par: &[u8]| u32::from_le_bytes(par[28..32].try_into().unwrap()) as usize;
+
+        let mut par = nested_archive();
+        let offset = file_info(&par) + 12;
+        par[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(ParArchive::parse(&par), Err(ParError::FileOutOfBounds { path }) if path == "readme.txt"));
+
+        // An extended offset which doesn't fit in the archive, or in a usize on 32-bit targets
+        let mut par = nested_archive();
+        let offset = file_info(&par) + 20;
+        par[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(ParArchive::parse(&par), Err(ParError::FileOutOfBounds { .. })));
+    }
+
+    #[test]
+    fn folder_out_of_bounds() {
+        let par = archive(&[(".", 1, 5, 0, 0)], &[]);
+        assert!(matches!(ParArchive::parse(&par), Err(ParError::FolderOutOfBounds { .. })));
+    }
+
+    #[test]
+    fn truncated_tables() {
+        let par = nested_archive();
+        let file_info_offset = u32::from_le_bytes(par[28..32].try_into().unwrap()) as usize;
+        for len in [0x10, HEADER_LEN + NAME_LEN, file_info_offset - 1, file_info_offset + ENTRY_LEN] {
+            assert!(matches!(ParArchive::parse(&par[..len]), Err(ParError::Parse(_))), "{len} bytes");
+        }
+    }
+
+    #[test]
+    fn size_mismatch() {
+        let mut par = nested_archive();
+        let offset = u32::from_le_bytes(par[28..32].try_into().unwrap()) as usize + ENTRY_LEN + 4;
+        par[offset..offset + 4].copy_from_slice(&100_u32.to_le_bytes());
+        let archive = ParArchive::parse(&par).unwrap();
+        assert!(matches!(
+            archive.files[1].contents(),
+            Err(ParError::SizeMismatch { expected: 100, actual: 19, .. })
+        ));
+    }
+}
@@ -0,0 +1,713 @@
+//! This module holds the results of a batch run over many shaders (see `disasm_many`) and renders them
+//! as a browsable HTML report, JSON Lines, or JUnit XML.
+//!
+//! The HTML report is a directory with an `index.html` summary and a page per shader under `shaders/`.
+//! All styling and scripts are inline, so the directory can be zipped up and shared as-is.
+//!
+//! The JSON Lines and JUnit outputs are for tracking results over time in CI.
+
+use std::{fmt::Write as _, path::Path, time::Duration};
+
+use serde::Serialize;
+
+use crate::{analysis::Phase, db::ShaderStage};
+
+/// How a single stage of a shader fared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StageOutcome {
+    Success,
+    Failed,
+    /// The compiler crashed in its worker process
+    Crashed,
+    /// The compiler didn't finish in time in its worker process
+    TimedOut,
+}
+impl StageOutcome {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            StageOutcome::Success => "success",
+            StageOutcome::Failed => "failed",
+            StageOutcome::Crashed => "crashed",
+            StageOutcome::TimedOut => "timed out",
+        }
+    }
+}
+
+/// The result of compiling, disassembling and analysing one stage of a shader
+#[derive(Debug, Clone)]
+pub struct StageResult {
+    pub stage: ShaderStage,
+    pub outcome: StageOutcome,
+    /// The phase the stage failed in
+    pub phase: Option<Phase>,
+    /// Why the stage didn't succeed
+    pub message: Option<String>,
+    /// The AMDIL disassembly, if compilation got that far and it was kept
+    pub amdil: Option<String>,
+    /// The output of [crate::disasm::output_dependencies_report], if analysis succeeded and it was kept
+    pub dependencies: Option<String>,
+    /// Time spent compiling, including cache lookups
+    pub compile_time: Duration,
+    /// Time spent disassembling and analysing
+    pub analysis_time: Duration,
+}
+
+/// The result of processing one file, or one pair of .vso/.pso files
+#[derive(Debug, Clone)]
+pub struct UnitResult {
+    pub name: String,
+    /// The paths of the files in the unit
+    pub paths: Vec<String>,
+    pub warnings: Vec<String>,
+    /// Set if the unit failed before any stages could be processed, e.g. the container couldn't be parsed
+    pub error: Option<String>,
+    pub stages: Vec<StageResult>,
+    /// Time spent on the whole unit
+    pub duration: Duration,
+}
+impl UnitResult {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+            && self
+                .stages
+                .iter()
+                .all(|stage| stage.outcome == StageOutcome::Success)
+    }
+
+    /// The first stage which didn't succeed, if any
+    pub fn first_failed_stage(&self) -> Option<&StageResult> {
+        self.stages
+            .iter()
+            .find(|stage| stage.outcome != StageOutcome::Success)
+    }
+
+    /// The phase the unit failed in, if it did
+    pub fn failure_phase(&self) -> Option<Phase> {
+        if self.error.is_some() {
+            return Some(Phase::Container);
+        }
+        self.first_failed_stage().and_then(|stage| stage.phase)
+    }
+
+    /// The message describing why the unit failed, if it did
+    pub fn failure_message(&self) -> Option<String> {
+        if let Some(error) = &self.error {
+            return Some(error.clone());
+        }
+        self.first_failed_stage()
+            .map(|stage| stage.message.clone().unwrap_or_default())
+    }
+}
+
+/// Register name prefixes which are replaced by [normalize_failure_message] when followed by a number, e.g. `r12` or `cb0`
+const REGISTER_PREFIXES: [&str; 10] = ["r", "v", "o", "l", "x", "s", "t", "u", "cb", "icb"];
+
+/// Replaces a single word of a failure message with a placeholder if it's a number, address or register name
+fn normalize_word(word: &str) -> &str {
+    if word.chars().all(|c| c.is_ascii_digit()) {
+        return "<n>";
+    }
+    if let Some(hex) = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        if !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return "<addr>";
+        }
+    }
+    // Long runs of hex digits are addresses or hashes even without a 0x prefix
+    if word.len() >= 8 && word.chars().all(|c| c.is_ascii_hexdigit()) && word.chars().any(|c| c.is_ascii_digit()) {
+        return "<addr>";
+    }
+    let prefix_len = word.find(|c: char| c.is_ascii_digit()).unwrap_or(word.len());
+    let (prefix, number) = word.split_at(prefix_len);
+    if !number.is_empty()
+        && number.chars().all(|c| c.is_ascii_digit())
+        && REGISTER_PREFIXES.contains(&prefix.to_ascii_lowercase().as_str())
+    {
+        return "<reg>";
+    }
+    word
+}
+
+/// Reduces a failure message to a template, so messages which only differ in the details cluster together.
+///
+/// Quoted operands (in `'`, `"` or `` ` ``) become `<str>`, numbers become `<n>`,
+/// hex addresses become `<addr>`, and register names (see [REGISTER_PREFIXES]) become `<reg>`.
+pub fn normalize_failure_message(message: &str) -> String {
+    let mut normalized = String::with_capacity(message.len());
+    let mut chars = message.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if matches!(c, '\'' | '"' | '`') {
+            // Only treat it as a quote if it starts a word and is closed, so apostrophes (e.g. "couldn't") are left alone
+            let starts_word = !message[..start].ends_with(|prev: char| prev.is_ascii_alphanumeric());
+            if let Some(len) = message[start + 1..].find(c).filter(|_| starts_word) {
+                normalized.push_str("<str>");
+                while chars.peek().is_some_and(|(i, _)| *i <= start + 1 + len) {
+                    chars.next();
+                }
+                continue;
+            }
+            normalized.push(c);
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let mut end = start + c.len_utf8();
+            while let Some((i, next)) = chars.peek().copied() {
+                if !(next.is_ascii_alphanumeric() || next == '_') {
+                    break;
+                }
+                end = i + next.len_utf8();
+                chars.next();
+            }
+            normalized.push_str(normalize_word(&message[start..end]));
+        } else {
+            normalized.push(c);
+        }
+    }
+    normalized
+}
+
+/// Failed units whose messages have the same [normalize_failure_message] template
+#[derive(Debug, Clone)]
+pub struct FailureCluster {
+    pub template: String,
+    /// The message of the first unit in the cluster
+    pub example: String,
+    /// The number of distinct messages in the cluster
+    pub unique_messages: usize,
+    /// The indices of the units in the cluster
+    pub units: Vec<usize>,
+}
+
+/// Clusters the failed units by message template, largest cluster first.
+///
+/// Takes (index, unit) pairs, so a subset of units can be clustered while keeping their original indices.
+pub fn cluster_failures<'a>(units: impl IntoIterator<Item = (usize, &'a UnitResult)>) -> Vec<FailureCluster> {
+    let mut clusters: Vec<(FailureCluster, Vec<String>)> = vec![];
+    for (index, unit) in units {
+        let Some(message) = unit.failure_message() else {
+            continue;
+        };
+        let template = normalize_failure_message(&message);
+        match clusters.iter_mut().find(|(cluster, _)| cluster.template == template) {
+            Some((cluster, messages)) => {
+                cluster.units.push(index);
+                if !messages.contains(&message) {
+                    messages.push(message);
+                }
+            }
+            None => clusters.push((
+                FailureCluster {
+                    template,
+                    example: message.clone(),
+                    unique_messages: 0,
+                    units: vec![index],
+                },
+                vec![message],
+            )),
+        }
+    }
+
+    let mut clusters: Vec<_> = clusters
+        .into_iter()
+        .map(|(mut cluster, messages)| {
+            cluster.unique_messages = messages.len();
+            cluster
+        })
+        .collect();
+    clusters.sort_by(|a, b| b.units.len().cmp(&a.units.len()).then_with(|| a.template.cmp(&b.template)));
+    clusters
+}
+
+/// The failed units whose first failure was in one phase
+#[derive(Debug, Clone)]
+pub struct PhaseFailures {
+    pub phase: Phase,
+    /// The number of units which failed in this phase
+    pub units: usize,
+    /// How many of those units failed in each stage, in stage order.
+    /// Container failures happen before any stage, so they aren't counted here.
+    pub stages: Vec<(ShaderStage, usize)>,
+}
+
+/// Counts the failed units by the phase and stage they first failed in, in pipeline order.
+/// Phases nothing failed in are left out.
+pub fn failures_by_phase(units: &[UnitResult]) -> Vec<PhaseFailures> {
+    let mut phases: Vec<PhaseFailures> = vec![];
+    for unit in units {
+        let Some(phase) = unit.failure_phase() else {
+            continue;
+        };
+        let failures = match phases.iter_mut().position(|failures| failures.phase == phase) {
+            Some(i) => &mut phases[i],
+            None => {
+                phases.push(PhaseFailures {
+                    phase,
+                    units: 0,
+                    stages: vec![],
+                });
+                phases.last_mut().unwrap()
+            }
+        };
+        failures.units += 1;
+        if let Some(stage) = unit.first_failed_stage().filter(|_| unit.error.is_none()) {
+            match failures.stages.iter_mut().find(|(s, _)| *s == stage.stage) {
+                Some((_, count)) => *count += 1,
+                None => failures.stages.push((stage.stage, 1)),
+            }
+        }
+    }
+
+    phases.sort_by_key(|failures| failures.phase);
+    for failures in &mut phases {
+        failures.stages.sort();
+    }
+    phases
+}
+
+/// Escapes text for use in HTML or XML element content and attribute values
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            // Other control characters aren't allowed in XML 1.0 at all, even escaped, and can turn up in panic messages
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; vertical-align: top; }
+th.sortable { cursor: pointer; background: #eee; }
+th.sortable:hover { background: #ddd; }
+.success { color: #1a7f37; }
+.failed, .crashed, .timed-out { color: #cf222e; }
+pre { background: #f6f8fa; padding: 1em; overflow-x: auto; }
+details { margin: 0.5em 0; }
+summary { cursor: pointer; }
+";
+
+/// Sorts a table by a column when its header is clicked, toggling between ascending and descending
+const SORT_SCRIPT: &str = "
+document.querySelectorAll('th.sortable').forEach(th => th.addEventListener('click', () => {
+  const table = th.closest('table');
+  const body = table.tBodies[0];
+  const column = Array.from(th.parentNode.children).indexOf(th);
+  const ascending = th.dataset.order !== 'asc';
+  th.dataset.order = ascending ? 'asc' : 'desc';
+  const key = row => row.children[column].textContent;
+  Array.from(body.rows)
+    .sort((a, b) => key(a).localeCompare(key(b), undefined, { numeric: true }) * (ascending ? 1 : -1))
+    .forEach(row => body.appendChild(row));
+}));
+";
+
+fn page_start(html: &mut String, title: &str) {
+    write!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n",
+        escape(title)
+    )
+    .unwrap();
+}
+
+fn page_end(html: &mut String) {
+    writeln!(html, "<script>{SORT_SCRIPT}</script>\n</body>\n</html>").unwrap();
+}
+
+fn outcome_cell(outcome: StageOutcome, status: &str) -> String {
+    format!(
+        "<td class=\"{}\">{}</td>",
+        outcome.to_str().replace(' ', "-"),
+        escape(status)
+    )
+}
+
+/// The outcome of a stage, with the phase it failed in if it did
+fn stage_status(stage: &StageResult) -> String {
+    match stage.phase {
+        Some(phase) => format!("{} in {}", stage.outcome.to_str(), phase.to_str()),
+        None => stage.outcome.to_str().to_owned(),
+    }
+}
+
+/// The path of a unit's page, relative to the report directory
+fn unit_page_path(index: usize, unit: &UnitResult) -> String {
+    let sanitized: String = unit
+        .name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect();
+    format!("shaders/{index:05}_{sanitized}.html")
+}
+
+fn render_unit_page(unit: &UnitResult) -> String {
+    let mut html = String::new();
+    page_start(&mut html, &unit.name);
+    writeln!(html, "<p><a href=\"../index.html\">Back to summary</a></p>\n<h1>{}</h1>", escape(&unit.name)).unwrap();
+
+    if let Some(error) = &unit.error {
+        writeln!(html, "<p class=\"failed\">{}</p>", escape(error)).unwrap();
+    }
+    if !unit.warnings.is_empty() {
+        html.push_str("<h2>Warnings</h2>\n<ul>\n");
+        for warning in &unit.warnings {
+            writeln!(html, "<li>{}</li>", escape(warning)).unwrap();
+        }
+        html.push_str("</ul>\n");
+    }
+
+    for stage in &unit.stages {
+        writeln!(
+            html,
+            "<h2>{} shader: <span class=\"{}\">{}</span></h2>",
+            stage.stage.to_str(),
+            stage.outcome.to_str().replace(' ', "-"),
+            stage_status(stage)
+        )
+        .unwrap();
+        if let Some(message) = &stage.message {
+            writeln!(html, "<pre>{}</pre>", escape(message)).unwrap();
+        }
+        if let Some(dependencies) = &stage.dependencies {
+            writeln!(html, "<details open>\n<summary>Dependency report</summary>\n<pre>{}</pre>\n</details>", escape(dependencies)).unwrap();
+        }
+        if let Some(amdil) = &stage.amdil {
+            writeln!(html, "<details>\n<summary>AMDIL disassembly</summary>\n<pre>{}</pre>\n</details>", escape(amdil)).unwrap();
+        }
+    }
+
+    page_end(&mut html);
+    html
+}
+
+fn render_index(title: &str, summary_lines: &[String], units: &[UnitResult]) -> String {
+    let mut html = String::new();
+    page_start(&mut html, title);
+    writeln!(html, "<h1>{}</h1>", escape(title)).unwrap();
+
+    let successes = units.iter().filter(|unit| unit.succeeded()).count();
+    writeln!(html, "<p>Successes: {successes} out of {}</p>", units.len()).unwrap();
+    for line in summary_lines {
+        writeln!(html, "<p>{}</p>", escape(line)).unwrap();
+    }
+
+    // Every stage which appears in any unit gets a column
+    let mut stages: Vec<ShaderStage> = vec![];
+    for stage in units.iter().flat_map(|unit| &unit.stages) {
+        if !stages.contains(&stage.stage) {
+            stages.push(stage.stage);
+        }
+    }
+
+    html.push_str("<h2>Files</h2>\n<table>\n<thead><tr><th class=\"sortable\">File</th><th class=\"sortable\">Status</th><th class=\"sortable\">Warnings</th>");
+    for stage in &stages {
+        write!(html, "<th class=\"sortable\">{}</th>", stage.to_str()).unwrap();
+    }
+    html.push_str("</tr></thead>\n<tbody>\n");
+    for (index, unit) in units.iter().enumerate() {
+        write!(
+            html,
+            "<tr><td><a href=\"{}\">{}</a></td>",
+            unit_page_path(index, unit),
+            escape(&unit.name)
+        )
+        .unwrap();
+        match unit.failure_phase() {
+            Some(phase) => html.push_str(&outcome_cell(StageOutcome::Failed, &format!("failed in {}", phase.to_str()))),
+            None if unit.succeeded() => html.push_str(&outcome_cell(StageOutcome::Success, StageOutcome::Success.to_str())),
+            None => html.push_str(&outcome_cell(StageOutcome::Failed, StageOutcome::Failed.to_str())),
+        }
+        write!(html, "<td>{}</td>", unit.warnings.len()).unwrap();
+        for stage in &stages {
+            match unit.stages.iter().find(|result| result.stage == *stage) {
+                Some(result) => html.push_str(&outcome_cell(result.outcome, &stage_status(result))),
+                None => html.push_str("<td></td>"),
+            }
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>\n");
+
+    let phases = failures_by_phase(units);
+    if !phases.is_empty() {
+        html.push_str("<h2>Failures by phase</h2>\n<table>\n<thead><tr><th>Phase</th><th>Files</th>");
+        for stage in &stages {
+            write!(html, "<th>{}</th>", stage.to_str()).unwrap();
+        }
+        html.push_str("</tr></thead>\n<tbody>\n");
+        for failures in &phases {
+            write!(html, "<tr><td>{}</td><td>{}</td>", failures.phase.to_str(), failures.units).unwrap();
+            for stage in &stages {
+                match failures.stages.iter().find(|(s, _)| s == stage) {
+                    Some((_, count)) => write!(html, "<td>{count}</td>").unwrap(),
+                    None => html.push_str("<td></td>"),
+                }
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</tbody>\n</table>\n");
+    }
+
+    let clusters = cluster_failures(units.iter().enumerate());
+    let unique_messages: usize = clusters.iter().map(|cluster| cluster.unique_messages).sum();
+    writeln!(html, "<h2>Failures ({} clusters, {unique_messages} unique messages)</h2>", clusters.len()).unwrap();
+    for cluster in clusters {
+        writeln!(
+            html,
+            "<details>\n<summary>{} file(s): {}</summary>\n<p>Example: <code>{}</code></p>\n<ul>",
+            cluster.units.len(),
+            escape(&cluster.template),
+            escape(&cluster.example)
+        )
+        .unwrap();
+        for index in cluster.units {
+            let unit = &units[index];
+            writeln!(html, "<li><a href=\"{}\">{}</a></li>", unit_page_path(index, unit), escape(&unit.name)).unwrap();
+        }
+        html.push_str("</ul>\n</details>\n");
+    }
+
+    page_end(&mut html);
+    html
+}
+
+/// Writes the HTML report for `units` into `dir`, creating it if necessary.
+///
+/// `summary_lines` are shown at the top of the summary page, e.g. the compiler version and cache statistics.
+pub fn write_html_report(dir: &Path, title: &str, summary_lines: &[String], units: &[UnitResult]) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir.join("shaders"))?;
+    std::fs::write(dir.join("index.html"), render_index(title, summary_lines, units))?;
+    for (index, unit) in units.iter().enumerate() {
+        std::fs::write(dir.join(unit_page_path(index, unit)), render_unit_page(unit))?;
+    }
+    Ok(())
+}
+
+/// One line of the JSON Lines output
+#[derive(Serialize)]
+struct JsonUnitRecord<'a> {
+    name: &'a str,
+    paths: &'a [String],
+    success: bool,
+    error_phase: Option<&'static str>,
+    error: Option<String>,
+    /// The error with details stripped, see [normalize_failure_message]
+    error_template: Option<String>,
+    warnings: &'a [String],
+    duration_ms: f64,
+    stages: Vec<JsonStageRecord<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonStageRecord<'a> {
+    stage: &'static str,
+    outcome: &'static str,
+    error_phase: Option<&'static str>,
+    error: Option<&'a str>,
+    compile_ms: f64,
+    analysis_ms: f64,
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Writes one JSON object per unit, one per line
+pub fn write_jsonl(w: &mut impl std::io::Write, units: &[UnitResult]) -> std::io::Result<()> {
+    for unit in units {
+        let record = JsonUnitRecord {
+            name: &unit.name,
+            paths: &unit.paths,
+            success: unit.succeeded(),
+            error_phase: unit.failure_phase().map(Phase::to_str),
+            error: unit.failure_message(),
+            error_template: unit.failure_message().as_deref().map(normalize_failure_message),
+            warnings: &unit.warnings,
+            duration_ms: millis(unit.duration),
+            stages: unit
+                .stages
+                .iter()
+                .map(|stage| JsonStageRecord {
+                    stage: stage.stage.to_str(),
+                    outcome: stage.outcome.to_str(),
+                    error_phase: stage.phase.map(Phase::to_str),
+                    error: stage.message.as_deref(),
+                    compile_ms: millis(stage.compile_time),
+                    analysis_ms: millis(stage.analysis_time),
+                })
+                .collect(),
+        };
+        serde_json::to_writer(&mut *w, &record)?;
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Writes a JUnit XML test suite where each shader stage is a test case, classed by the unit it came from.
+///
+/// Units with a container error get an extra `container` test case alongside any stages which were still processed.
+/// Crashes and timeouts are reported as errors, other failures as failures.
+pub fn write_junit(w: &mut impl std::io::Write, suite_name: &str, units: &[UnitResult]) -> std::io::Result<()> {
+    let mut cases = String::new();
+    let (mut tests, mut failures, mut errors) = (0, 0, 0);
+    let mut total_time = Duration::ZERO;
+
+    for unit in units {
+        total_time += unit.duration;
+        if let Some(error) = &unit.error {
+            tests += 1;
+            failures += 1;
+            writeln!(
+                cases,
+                "    <testcase classname=\"{}\" name=\"container\" time=\"{:.3}\">\n      <failure type=\"{}\" message=\"{}\"/>\n    </testcase>",
+                escape(&unit.name),
+                unit.duration.as_secs_f64(),
+                Phase::Container.to_str(),
+                escape(error)
+            )
+            .unwrap();
+        }
+        // A unit can fail after some of its stages were analysed, e.g. when the .pso of a pair is broken
+        for stage in &unit.stages {
+            tests += 1;
+            let time = (stage.compile_time + stage.analysis_time).as_secs_f64();
+            write!(
+                cases,
+                "    <testcase classname=\"{}\" name=\"{}\" time=\"{time:.3}\"",
+                escape(&unit.name),
+                stage.stage.to_str()
+            )
+            .unwrap();
+            let element = match stage.outcome {
+                StageOutcome::Success => {
+                    cases.push_str("/>\n");
+                    continue;
+                }
+                StageOutcome::Failed => {
+                    failures += 1;
+                    "failure"
+                }
+                StageOutcome::Crashed | StageOutcome::TimedOut => {
+                    errors += 1;
+                    "error"
+                }
+            };
+            let message = escape(stage.message.as_deref().unwrap_or_default());
+            writeln!(
+                cases,
+                ">\n      <{element} type=\"{}\" message=\"{message}\">{message}</{element}>\n    </testcase>",
+                stage.phase.map_or(stage.outcome.to_str(), Phase::to_str)
+            )
+            .unwrap();
+        }
+    }
+
+    writeln!(w, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(w, "<testsuites>")?;
+    writeln!(
+        w,
+        "  <testsuite name=\"{}\" tests=\"{tests}\" failures=\"{failures}\" errors=\"{errors}\" time=\"{:.3}\">",
+        escape(suite_name),
+        total_time.as_secs_f64()
+    )?;
+    w.write_all(cases.as_bytes())?;
+    writeln!(w, "  </testsuite>")?;
+    writeln!(w, "</testsuites>")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stage(stage: ShaderStage, outcome: StageOutcome, message: Option<&str>) -> StageResult {
+        StageResult {
+            stage,
+            outcome,
+            phase: message.map(|_| Phase::Compile),
+            message: message.map(str::to_owned),
+            amdil: None,
+            dependencies: None,
+            compile_time: Duration::ZERO,
+            analysis_time: Duration::ZERO,
+        }
+    }
+
+    fn failed_unit(name: &str, message: &str) -> UnitResult {
+        UnitResult {
+            name: name.to_owned(),
+            paths: vec![],
+            warnings: vec![],
+            error: None,
+            stages: vec![stage(ShaderStage::Vertex, StageOutcome::Failed, Some(message))],
+            duration: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn normalize_failure_messages() {
+        assert_eq!(
+            normalize_failure_message("couldn't decode instruction"),
+            "couldn't decode instruction"
+        );
+        assert_eq!(
+            normalize_failure_message("unknown opcode 'dcl_foo' in \"main\""),
+            "unknown opcode <str> in <str>"
+        );
+        assert_eq!(
+            normalize_failure_message("access violation at 0x7ffb1234 reading 0XDEAD"),
+            "access violation at <addr> reading <addr>"
+        );
+        assert_eq!(
+            normalize_failure_message("r12 read before write, cb0[3] out of range"),
+            "<reg> read before write, <reg>[<n>] out of range"
+        );
+        // Words which only look like registers are kept
+        assert_eq!(normalize_failure_message("dx11 and ps_5_0"), "dx11 and ps_5_0");
+    }
+
+    #[test]
+    fn cluster_failures_by_template() {
+        let units = [
+            failed_unit("a", "r1 used before write at 0x10"),
+            failed_unit("b", "r7 used before write at 0x2c"),
+            failed_unit("c", "r1 used before write at 0x10"),
+            failed_unit("d", "couldn't find 'SV_Position'"),
+        ];
+        let clusters = cluster_failures(units.iter().enumerate());
+
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].template, "<reg> used before write at <addr>");
+        assert_eq!(clusters[0].units, [0, 1, 2]);
+        assert_eq!(clusters[0].unique_messages, 2);
+        assert_eq!(clusters[0].example, "r1 used before write at 0x10");
+        assert_eq!(clusters[1].template, "couldn't find <str>");
+        assert_eq!(clusters[1].units, [3]);
+    }
+
+    #[test]
+    fn junit_keeps_stages_of_units_with_container_errors() {
+        let unit = UnitResult {
+            name: "pair".to_owned(),
+            paths: vec![],
+            warnings: vec![],
+            error: Some("bad\u{1}.pso".to_owned()),
+            stages: vec![stage(ShaderStage::Vertex, StageOutcome::Success, None)],
+            duration: Duration::ZERO,
+        };
+        let mut xml = vec![];
+        write_junit(&mut xml, "suite", &[unit]).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+
+        assert!(xml.contains("tests=\"2\" failures=\"1\" errors=\"0\""));
+        assert!(xml.contains("name=\"container\""));
+        assert!(xml.contains("message=\"bad.pso\""));
+        assert!(xml.contains(&format!("name=\"{}\"", ShaderStage::Vertex.to_str())));
+    }
+}
@@ -0,0 +1,465 @@
+//! This module implements the SLLZ compression format used by Dragon Engine archives.
+//!
+//! # Header
+//! Every SLLZ stream starts with a 16 byte header.
+//! The integers in the header use the endianness given by the endianness byte (0 = little-endian).
+//! - The magic "SLLZ"
+//! - u8 endianness
+//! - u8 version (1 or 2)
+//! - u16 header size
+//! - u32 decompressed size
+//! - u32 compressed size, including the header
+//!
+//! # Version 1
+//! An LZ77 variant. A flag byte describes the next 8 tokens MSB-first:
+//! a 0 bit is a literal byte, and a 1 bit is a little-endian u16 back-reference
+//! with `(token >> 4) + 1` distance and `(token & 0xF) + 3` length.
+//! The next flag byte is read as soon as the last bit of the previous one is consumed,
+//! i.e. before the data of the 8th token.
+//!
+//! # Version 2
+//! A sequence of zlib-compressed chunks, each decompressing to at most 64KiB.
+//! Each chunk starts with a 5 byte header: a big-endian u24 size of the whole chunk (including the header),
+//! then a big-endian u16 of the decompressed chunk size minus 1.
+
+use std::io::{Read, Write};
+
+const HEADER_LEN: usize = 0x10;
+
+const V1_MAX_DISTANCE: usize = 0x1000;
+const V1_MIN_LENGTH: usize = 3;
+const V1_MAX_LENGTH: usize = 0xF + V1_MIN_LENGTH;
+
+const V2_CHUNK_HEADER_LEN: usize = 5;
+const V2_MAX_CHUNK_LEN: usize = 0x10000;
+
+/// The most a byte of version 1 data can expand to: a 2 byte back-reference of the maximum length
+const V1_MAX_RATIO: usize = V1_MAX_LENGTH / 2;
+/// The most a byte of deflate data can expand to
+const V2_MAX_RATIO: usize = 1032;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SllzVersion {
+    V1,
+    V2,
+}
+
+#[derive(Debug)]
+pub enum SllzError {
+    /// The data doesn't start with an SLLZ header
+    NotSllz,
+    UnsupportedVersion(u8),
+    /// The compressed data ended before the full decompressed size was produced
+    Truncated,
+    /// A version 1 back-reference pointed before the start of the output
+    InvalidBackReference { output_pos: usize, distance: usize },
+    /// A version 2 chunk failed to decompress
+    Zlib(std::io::Error),
+    /// The decompressed data was a different size to that stated in the header
+    SizeMismatch { expected: usize, actual: usize },
+}
+impl std::fmt::Display for SllzError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SllzError::NotSllz => write!(f, "missing SLLZ header"),
+            SllzError::UnsupportedVersion(version) => {
+                write!(f, "SLLZ version {version} is not supported")
+            }
+            SllzError::Truncated => write!(f, "SLLZ data is truncated"),
+            SllzError::InvalidBackReference { output_pos, distance } => write!(
+                f,
+                "SLLZ back-reference at output position {output_pos} has distance {distance}, which is before the start of the output"
+            ),
+            SllzError::Zlib(e) => write!(f, "SLLZ chunk failed to decompress: {e}"),
+            SllzError::SizeMismatch { expected, actual } => write!(
+                f,
+                "SLLZ data decompressed to {actual} bytes, but the header says {expected}"
+            ),
+        }
+    }
+}
+impl std::error::Error for SllzError {}
+
+/// Returns true if the bytes start with the SLLZ magic
+pub fn is_sllz(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"SLLZ")
+}
+
+/// Decompresses an SLLZ stream of either version
+pub fn decompress(input: &[u8]) -> Result<Vec<u8>, SllzError> {
+    if input.len() < HEADER_LEN || !is_sllz(input) {
+        return Err(SllzError::NotSllz);
+    }
+    let big_endian = input[4] != 0;
+    let version = input[5];
+    let read_u16 = |b: &[u8]| {
+        let b = [b[0], b[1]];
+        if big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) }
+    };
+    let read_u32 = |b: &[u8]| {
+        let b = [b[0], b[1], b[2], b[3]];
+        if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) }
+    };
+    let header_len = read_u16(&input[6..8]) as usize;
+    let decompressed_len = read_u32(&input[8..12]) as usize;
+    let compressed_len = read_u32(&input[12..16]) as usize;
+
+    let data = input.get(header_len..compressed_len).ok_or(SllzError::Truncated)?;
+    let output = match version {
+        1 => decompress_v1(data, decompressed_len)?,
+        2 => decompress_v2(data, decompressed_len)?,
+        _ => return Err(SllzError::UnsupportedVersion(version)),
+    };
+
+    if output.len() != decompressed_len {
+        return Err(SllzError::SizeMismatch {
+            expected: decompressed_len,
+            actual: output.len(),
+        });
+    }
+    Ok(output)
+}
+
+/// The capacity to allocate for the output up front.
+/// The decompressed size comes from the header, so it's capped at what the data could possibly decompress to.
+fn initial_capacity(data: &[u8], decompressed_len: usize, max_ratio: usize) -> usize {
+    decompressed_len.min(data.len().saturating_mul(max_ratio))
+}
+
+fn decompress_v1(data: &[u8], decompressed_len: usize) -> Result<Vec<u8>, SllzError> {
+    let mut output = Vec::with_capacity(initial_capacity(data, decompressed_len, V1_MAX_RATIO));
+    if decompressed_len == 0 {
+        return Ok(output);
+    }
+
+    let mut pos = 0;
+    let mut flags = *data.get(pos).ok_or(SllzError::Truncated)?;
+    pos += 1;
+    let mut flags_left = 8;
+    while output.len() < decompressed_len {
+        let is_copy = (flags & 0x80) != 0;
+        flags <<= 1;
+        flags_left -= 1;
+        if flags_left == 0 {
+            flags = *data.get(pos).ok_or(SllzError::Truncated)?;
+            pos += 1;
+            flags_left = 8;
+        }
+
+        if is_copy {
+            let token = data.get(pos..(pos + 2)).ok_or(SllzError::Truncated)?;
+            pos += 2;
+            let token = u16::from_le_bytes([token[0], token[1]]) as usize;
+            let distance = (token >> 4) + 1;
+            let length = (token & 0xF) + V1_MIN_LENGTH;
+            if distance > output.len() {
+                return Err(SllzError::InvalidBackReference {
+                    output_pos: output.len(),
+                    distance,
+                });
+            }
+            for _ in 0..length {
+                output.push(output[output.len() - distance]);
+            }
+        } else {
+            output.push(*data.get(pos).ok_or(SllzError::Truncated)?);
+            pos += 1;
+        }
+    }
+    // The last back-reference may run past the end of the file
+    output.truncate(decompressed_len);
+
+    Ok(output)
+}
+
+fn decompress_v2(data: &[u8], decompressed_len: usize) -> Result<Vec<u8>, SllzError> {
+    let mut output = Vec::with_capacity(initial_capacity(data, decompressed_len, V2_MAX_RATIO));
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let header = data
+            .get(pos..(pos + V2_CHUNK_HEADER_LEN))
+            .ok_or(SllzError::Truncated)?;
+        let chunk_len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+        let chunk_decompressed_len = u16::from_be_bytes([header[3], header[4]]) as usize + 1;
+        if chunk_len < V2_CHUNK_HEADER_LEN {
+            return Err(SllzError::Truncated);
+        }
+        let chunk = data
+            .get((pos + V2_CHUNK_HEADER_LEN)..(pos + chunk_len))
+            .ok_or(SllzError::Truncated)?;
+
+        // Read at most one byte more than expected, so a chunk which decompresses to far more is caught without reading it all
+        let start = output.len();
+        flate2::read::ZlibDecoder::new(chunk)
+            .take(chunk_decompressed_len as u64 + 1)
+            .read_to_end(&mut output)
+            .map_err(SllzError::Zlib)?;
+        if output.len() - start != chunk_decompressed_len || output.len() > decompressed_len {
+            return Err(SllzError::SizeMismatch {
+                expected: start + chunk_decompressed_len,
+                actual: output.len(),
+            });
+        }
+
+        pos += chunk_len;
+    }
+
+    Ok(output)
+}
+
+/// Compresses data into a little-endian SLLZ stream of the given version
+pub fn compress(input: &[u8], version: SllzVersion) -> Vec<u8> {
+    let data = match version {
+        SllzVersion::V1 => compress_v1(input),
+        SllzVersion::V2 => compress_v2(input),
+    };
+
+    let mut output = Vec::with_capacity(HEADER_LEN + data.len());
+    output.extend_from_slice(b"SLLZ");
+    output.push(0); // little-endian
+    output.push(match version {
+        SllzVersion::V1 => 1,
+        SllzVersion::V2 => 2,
+    });
+    output.extend_from_slice(&(HEADER_LEN as u16).to_le_bytes());
+    output.extend_from_slice(&(input.len() as u32).to_le_bytes());
+    output.extend_from_slice(&((HEADER_LEN + data.len()) as u32).to_le_bytes());
+    output.extend_from_slice(&data);
+    output
+}
+
+fn compress_v1(input: &[u8]) -> Vec<u8> {
+    let mut output = vec![];
+    if input.is_empty() {
+        return output;
+    }
+
+    // Recent positions of every 3-byte sequence, for finding back-references
+    let mut prefix_positions: std::collections::HashMap<[u8; 3], Vec<usize>> = Default::default();
+    let record_prefix = |prefix_positions: &mut std::collections::HashMap<[u8; 3], Vec<usize>>, pos: usize| {
+        if pos + V1_MIN_LENGTH <= input.len() {
+            prefix_positions
+                .entry([input[pos], input[pos + 1], input[pos + 2]])
+                .or_default()
+                .push(pos);
+        }
+    };
+
+    let mut flag_pos = output.len();
+    output.push(0);
+    let mut flags_used = 0;
+
+    let mut pos = 0;
+    while pos < input.len() {
+        let mut best_len = 0;
+        let mut best_distance = 0;
+        if pos + V1_MIN_LENGTH <= input.len() {
+            let prefix = [input[pos], input[pos + 1], input[pos + 2]];
+            if let Some(candidates) = prefix_positions.get(&prefix) {
+                for &candidate in candidates.iter().rev() {
+                    let distance = pos - candidate;
+                    if distance > V1_MAX_DISTANCE {
+                        break;
+                    }
+                    let max_len = V1_MAX_LENGTH.min(input.len() - pos);
+                    let len = (0..max_len)
+                        .take_while(|i| input[candidate + i] == input[pos + i])
+                        .count();
+                    if len > best_len {
+                        best_len = len;
+                        best_distance = distance;
+                        if len == V1_MAX_LENGTH {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        let is_copy = best_len >= V1_MIN_LENGTH;
+        if is_copy {
+            output[flag_pos] |= 0x80 >> flags_used;
+        }
+        flags_used += 1;
+        if flags_used == 8 {
+            flag_pos = output.len();
+            output.push(0);
+            flags_used = 0;
+        }
+
+        if is_copy {
+            let token = ((best_distance - 1) << 4) | (best_len - V1_MIN_LENGTH);
+            output.extend_from_slice(&(token as u16).to_le_bytes());
+            for i in pos..(pos + best_len) {
+                record_prefix(&mut prefix_positions, i);
+            }
+            pos += best_len;
+        } else {
+            output.push(input[pos]);
+            record_prefix(&mut prefix_positions, pos);
+            pos += 1;
+        }
+    }
+
+    output
+}
+
+fn compress_v2(input: &[u8]) -> Vec<u8> {
+    let mut output = vec![];
+    for chunk in input.chunks(V2_MAX_CHUNK_LEN) {
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder
+            .write_all(chunk)
+            .expect("writing to a Vec can't fail");
+        let compressed = encoder.finish().expect("writing to a Vec can't fail");
+
+        let chunk_len = (V2_CHUNK_HEADER_LEN + compressed.len()) as u32;
+        output.extend_from_slice(&chunk_len.to_be_bytes()[1..]);
+        output.extend_from_slice(&((chunk.len() - 1) as u16).to_be_bytes());
+        output.extend_from_slice(&compressed);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random bytes, which shouldn't compress well
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state: u32 = 0x1234_5678;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    /// Text-like bytes with lots of short repeats
+    fn repetitive(len: usize) -> Vec<u8> {
+        b"dcl_input_generic v0.xyzw\nmov r0, cb0[1]\n"
+            .iter()
+            .cycle()
+            .take(len)
+            .copied()
+            .collect()
+    }
+
+    fn assert_round_trip(input: &[u8], version: SllzVersion) {
+        let compressed = compress(input, version);
+        assert!(is_sllz(&compressed));
+        let decompressed = decompress(&compressed).expect("failed to decompress");
+        assert_eq!(input, decompressed.as_slice());
+    }
+
+    #[test]
+    fn round_trip_v1() {
+        assert_round_trip(b"", SllzVersion::V1);
+        assert_round_trip(b"a", SllzVersion::V1);
+        assert_round_trip(b"abcdefgh", SllzVersion::V1);
+        assert_round_trip(b"abcdefghi", SllzVersion::V1);
+        assert_round_trip(&[0; 1000], SllzVersion::V1);
+        assert_round_trip(&repetitive(10_000), SllzVersion::V1);
+        assert_round_trip(&noise(10_000), SllzVersion::V1);
+        let mut mixed = noise(5000);
+        mixed.extend(repetitive(5000));
+        mixed.extend(noise(5000));
+        mixed.extend_from_within(0..5000);
+        assert_round_trip(&mixed, SllzVersion::V1);
+    }
+
+    #[test]
+    fn round_trip_v2() {
+        assert_round_trip(b"", SllzVersion::V2);
+        assert_round_trip(b"a", SllzVersion::V2);
+        assert_round_trip(&repetitive(10_000), SllzVersion::V2);
+        assert_round_trip(&noise(10_000), SllzVersion::V2);
+        // Multiple chunks, including one exactly the maximum size
+        assert_round_trip(&repetitive(V2_MAX_CHUNK_LEN), SllzVersion::V2);
+        assert_round_trip(&noise(3 * V2_MAX_CHUNK_LEN + 17), SllzVersion::V2);
+    }
+
+    #[test]
+    fn v1_compresses_repeats() {
+        let input = repetitive(10_000);
+        assert!(compress(&input, SllzVersion::V1).len() < input.len() / 4);
+    }
+
+    #[test]
+    fn decompress_v1_handwritten() {
+        // "abc" as literals, then a back-reference with distance 3 and length 6, then "d" as a literal
+        let data = [0b0001_0000, b'a', b'b', b'c', 0x23, 0x00, b'd'];
+        let mut input = b"SLLZ\x00\x01\x10\x00".to_vec();
+        input.extend_from_slice(&10u32.to_le_bytes());
+        input.extend_from_slice(&((HEADER_LEN + data.len()) as u32).to_le_bytes());
+        input.extend_from_slice(&data);
+        assert_eq!(decompress(&input).unwrap(), b"abcabcabcd");
+    }
+
+    #[test]
+    fn decompress_big_endian_header() {
+        let compressed = compress(b"big endian header", SllzVersion::V1);
+        let mut big_endian = compressed.clone();
+        big_endian[4] = 1;
+        big_endian[6..8].copy_from_slice(&(HEADER_LEN as u16).to_be_bytes());
+        big_endian[8..12].copy_from_slice(&17u32.to_be_bytes());
+        big_endian[12..16].copy_from_slice(&(compressed.len() as u32).to_be_bytes());
+        assert_eq!(decompress(&big_endian).unwrap(), b"big endian header");
+    }
+
+    #[test]
+    fn decompress_errors() {
+        assert!(matches!(decompress(b"not sllz at all!"), Err(SllzError::NotSllz)));
+
+        let mut wrong_version = compress(b"abc", SllzVersion::V1);
+        wrong_version[5] = 3;
+        assert!(matches!(decompress(&wrong_version), Err(SllzError::UnsupportedVersion(3))));
+
+        for version in [SllzVersion::V1, SllzVersion::V2] {
+            let mut truncated = compress(&noise(100), version);
+            truncated.truncate(truncated.len() - 10);
+            assert!(decompress(&truncated).is_err());
+        }
+
+        // A back-reference as the first token
+        let mut bad_reference = b"SLLZ\x00\x01\x10\x00".to_vec();
+        bad_reference.extend_from_slice(&3u32.to_le_bytes());
+        bad_reference.extend_from_slice(&((HEADER_LEN + 3) as u32).to_le_bytes());
+        bad_reference.extend_from_slice(&[0x80, 0x00, 0x00]);
+        assert!(matches!(
+            decompress(&bad_reference),
+            Err(SllzError::InvalidBackReference { output_pos: 0, distance: 1 })
+        ));
+    }
+
+    #[test]
+    fn huge_decompressed_size_is_truncated() {
+        let mut header = compress(b"", SllzVersion::V1);
+        header[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(decompress(&header), Err(SllzError::Truncated)));
+    }
+
+    #[test]
+    fn v2_chunk_larger_than_stated() {
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&[0; 1 << 20]).unwrap();
+        let bomb = encoder.finish().unwrap();
+
+        let mut compressed = compress(b"", SllzVersion::V2);
+        compressed.extend_from_slice(&((V2_CHUNK_HEADER_LEN + bomb.len()) as u32).to_be_bytes()[1..]);
+        compressed.extend_from_slice(&0_u16.to_be_bytes());
+        compressed.extend_from_slice(&bomb);
+        let len = compressed.len() as u32;
+        compressed[8..12].copy_from_slice(&1_u32.to_le_bytes());
+        compressed[12..16].copy_from_slice(&len.to_le_bytes());
+
+        assert!(matches!(
+            decompress(&compressed),
+            Err(SllzError::SizeMismatch { expected: 1, actual: 2 })
+        ));
+    }
+}
@@ -0,0 +1,210 @@
+//! This module gathers shader files from the paths given to the command-line tools,
+//! which may be single files, directories, or `.par` archives.
+
+use std::{collections::HashMap, path::Path};
+
+use crate::{db::ShaderStage, par::ParArchive, sllz, yk::YkContainerKind};
+
+/// A file read from disk or extracted from a `.par` archive
+pub struct ShaderFile {
+    /// The path of the file on disk, or `archive.par/path/inside/archive` for files inside an archive
+    pub path: String,
+    pub data: Vec<u8>,
+}
+impl ShaderFile {
+    /// The file name without any folders
+    pub fn name(&self) -> &str {
+        self.path
+            .rsplit(['/', '\\'])
+            .next()
+            .unwrap_or(&self.path)
+    }
+
+    /// The file name up to the first '.', which names the shader, e.g. `foo` for `foo.vso`
+    pub fn stem(&self) -> &str {
+        let name = self.name();
+        name.split_once('.').map_or(name, |(stem, _)| stem)
+    }
+}
+
+/// A group of shader files which are analysed together
+pub enum ShaderUnit {
+    /// A single container, e.g. an `.fxo` or a `.vso` without a matching `.pso`
+    Single(ShaderFile),
+    /// A legacy `.vso` and `.pso` with the same name
+    Pair { vso: ShaderFile, pso: ShaderFile },
+}
+impl ShaderUnit {
+    /// A human-readable name for the unit
+    pub fn name(&self) -> String {
+        match self {
+            ShaderUnit::Single(file) => file.path.clone(),
+            ShaderUnit::Pair { vso, pso } => format!("{} + {}", vso.path, pso.name()),
+        }
+    }
+
+    pub fn files(&self) -> Vec<&ShaderFile> {
+        match self {
+            ShaderUnit::Single(file) => vec![file],
+            ShaderUnit::Pair { vso, pso } => vec![vso, pso],
+        }
+    }
+}
+
+/// Groups shader containers into units for analysis, based on their magic rather than their extension.
+///
+/// Standalone vertex and pixel shader containers with the same [ShaderFile::stem] and folder are paired together.
+/// Files which aren't shader containers are dropped.
+pub fn group_shader_files(files: Vec<ShaderFile>) -> Vec<ShaderUnit> {
+    let pair_key = |file: &ShaderFile| {
+        let folder = &file.path[..(file.path.len() - file.name().len())];
+        format!("{folder}{}", file.stem())
+    };
+
+    let mut units = vec![];
+    let mut vsos = vec![];
+    let mut psos: HashMap<String, ShaderFile> = HashMap::new();
+    for file in files {
+        match YkContainerKind::detect(&file.data) {
+            Some(YkContainerKind::Standalone(ShaderStage::Vertex)) => vsos.push(file),
+            Some(YkContainerKind::Standalone(ShaderStage::Fragment)) => {
+                psos.insert(pair_key(&file), file);
+            }
+            Some(_) => units.push(ShaderUnit::Single(file)),
+            None => {}
+        }
+    }
+    for vso in vsos {
+        match psos.remove(&pair_key(&vso)) {
+            Some(pso) => units.push(ShaderUnit::Pair { vso, pso }),
+            None => units.push(ShaderUnit::Single(vso)),
+        }
+    }
+    let mut unpaired_psos: Vec<_> = psos.into_values().collect();
+    unpaired_psos.sort_by(|a, b| a.path.cmp(&b.path));
+    units.extend(unpaired_psos.into_iter().map(ShaderUnit::Single));
+
+    units
+}
+
+/// A file which couldn't be read or decompressed, and was skipped
+#[derive(Debug, Clone)]
+pub struct ReadFailure {
+    pub path: String,
+    pub error: String,
+}
+
+/// The files found by [read_shader_files]
+#[derive(Default)]
+pub struct ShaderFiles {
+    pub files: Vec<ShaderFile>,
+    /// Files which were skipped because they couldn't be read, so one bad file doesn't stop the others being analysed
+    pub failures: Vec<ReadFailure>,
+}
+
+/// Reads the files at `path` whose names are accepted by `filter`.
+///
+/// `path` may be a single file, a directory (non-recursive, subdirectories are skipped), or a `.par` archive.
+/// `.par` archives, whether passed directly, found in the directory, or nested inside other archives,
+/// are expanded into the files they contain.
+/// SLLZ-compressed files are decompressed.
+///
+/// Only fails if `path` itself can't be read. Files which can't be read, decompressed or extracted from an archive
+/// are listed in [ShaderFiles::failures], so one corrupt archive doesn't stop the rest being analysed.
+pub fn read_shader_files<F: Fn(&str) -> bool>(path: &Path, filter: F) -> std::io::Result<ShaderFiles> {
+    let mut files = ShaderFiles::default();
+    if path.is_dir() {
+        let mut entries = std::fs::read_dir(path)?.collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if !entry.path().is_file() || !(name.ends_with(".par") || filter(name)) {
+                continue;
+            }
+            let path = entry.path().to_string_lossy().into_owned();
+            match std::fs::read(entry.path()) {
+                Ok(data) => push_file(path, data, &filter, &mut files),
+                Err(e) => files.failures.push(ReadFailure {
+                    path,
+                    error: e.to_string(),
+                }),
+            }
+        }
+    } else {
+        push_file(
+            path.to_string_lossy().into_owned(),
+            std::fs::read(path)?,
+            &filter,
+            &mut files,
+        );
+    }
+    Ok(files)
+}
+
+/// Pushes a file into `files`, decompressing it if necessary, or if it is a `.par` archive pushes every file inside it which passes the filter.
+/// Files which can't be decompressed or extracted are pushed into [ShaderFiles::failures] instead.
+fn push_file<F: Fn(&str) -> bool>(path: String, data: Vec<u8>, filter: &F, files: &mut ShaderFiles) {
+    if sllz::is_sllz(&data) {
+        match sllz::decompress(&data) {
+            Ok(data) => push_file(path, data, filter, files),
+            Err(e) => files.failures.push(ReadFailure {
+                path,
+                error: format!("couldn't decompress: {e}"),
+            }),
+        }
+        return;
+    }
+    if !ParArchive::is_par(&data) {
+        files.files.push(ShaderFile { path, data });
+        return;
+    }
+
+    let archive = match ParArchive::parse(&data) {
+        Ok(archive) => archive,
+        Err(e) => {
+            files.failures.push(ReadFailure {
+                path,
+                error: e.to_string(),
+            });
+            return;
+        }
+    };
+    for file in archive.files {
+        if file.name().ends_with(".par") || filter(file.name()) {
+            let file_path = format!("{path}/{}", file.path);
+            match file.contents() {
+                Ok(contents) => push_file(file_path, contents.into_owned(), filter, files),
+                Err(e) => files.failures.push(ReadFailure {
+                    path: file_path,
+                    error: e.to_string(),
+                }),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unreadable_entries_are_failures() {
+        let dir = std::env::temp_dir().join(format!("yk_fxo_disasm_source_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("subdir.fxo")).unwrap();
+        std::fs::write(dir.join("a.fxo"), b"GSFX").unwrap();
+        std::fs::write(dir.join("b.fxo"), b"SLLZ\0\x01").unwrap();
+        std::fs::write(dir.join("c.par"), b"PARC\0").unwrap();
+
+        let files = read_shader_files(&dir, |_| true).unwrap();
+        let paths: Vec<_> = files.files.iter().map(ShaderFile::name).collect();
+        assert_eq!(paths, ["a.fxo"]);
+        assert_eq!(files.failures.len(), 2);
+        assert!(files.failures[0].path.ends_with("b.fxo"));
+        assert!(files.failures[1].path.ends_with("c.par"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
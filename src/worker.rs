@@ -0,0 +1,320 @@
+//! This module runs compilations in a separate worker process, so a shader which crashes or hangs the compiler
+//! only takes down the worker.
+//!
+//! The [Supervisor] starts a worker process and talks to it over its stdin/stdout.
+//! The worker process calls [run_worker] with a [CompileBackend] which does the actual compilation.
+//! If the worker crashes or doesn't respond within the timeout, it is killed and a new one is started for the next compilation.
+//!
+//! # Protocol
+//! Every message is a sequence of frames, each of which is [FRAME_MAGIC], a little-endian u32 length, then that many bytes.
+//! The reader skips anything before a frame magic, so stray output from the compiler on stdout doesn't break the protocol.
+//!
+//! - A request is a frame with the [AsicTarget] name followed by a frame with the DXBC.
+//! - A response is a frame with the status (`ok` or `err`) followed by a frame with the ELF or the error message.
+
+use std::{
+    io::{BufReader, ErrorKind, Read, Write},
+    path::PathBuf,
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use crate::{
+    compile::{CompileBackend, CompileError},
+    db::AsicTarget,
+};
+
+/// Marks the start of every frame
+pub const FRAME_MAGIC: &[u8; 4] = b"YKCW";
+
+fn write_frame(w: &mut impl Write, data: &[u8]) -> std::io::Result<()> {
+    w.write_all(FRAME_MAGIC)?;
+    w.write_all(&(data.len() as u32).to_le_bytes())?;
+    w.write_all(data)
+}
+
+/// Reads the next frame, skipping any bytes before its magic.
+/// Returns None if the stream ends before a frame starts.
+fn read_frame(r: &mut impl Read) -> std::io::Result<Option<Vec<u8>>> {
+    let mut window = [0u8; 4];
+    let mut seen = 0;
+    while seen < 4 || &window != FRAME_MAGIC {
+        let mut byte = [0u8];
+        if r.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        window.rotate_left(1);
+        window[3] = byte[0];
+        seen += 1;
+    }
+
+    let mut len = [0u8; 4];
+    r.read_exact(&mut len)?;
+    let mut data = vec![0u8; u32::from_le_bytes(len) as usize];
+    r.read_exact(&mut data)?;
+    Ok(Some(data))
+}
+
+/// Reads a frame which must be present, as part of a message which has already started
+fn read_required_frame(r: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    read_frame(r)?.ok_or_else(|| std::io::Error::new(ErrorKind::UnexpectedEof, "message ended early"))
+}
+
+/// Serves compilation requests from `input` until it is closed, writing responses to `output`.
+///
+/// Compilation errors are sent back to the supervisor, so only I/O errors end the loop early.
+pub fn run_worker(backend: &impl CompileBackend, mut input: impl Read, mut output: impl Write) -> std::io::Result<()> {
+    while let Some(target) = read_frame(&mut input)? {
+        let dxbc = read_required_frame(&mut input)?;
+        let target = std::str::from_utf8(&target)
+            .ok()
+            .and_then(|target| AsicTarget::try_from(target).ok())
+            .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, "invalid target in request"))?;
+
+        match backend.compile_elf(&dxbc, target) {
+            Ok(elf) => {
+                write_frame(&mut output, b"ok")?;
+                write_frame(&mut output, &elf)?;
+            }
+            Err(e) => {
+                write_frame(&mut output, b"err")?;
+                write_frame(&mut output, e.to_string().as_bytes())?;
+            }
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// A response from the worker: the ELF, or the error message
+type WorkerResponse = Result<Vec<u8>, String>;
+
+/// A running worker process
+struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    /// Responses read from the worker's stdout by a separate thread, so waiting for them can time out.
+    /// The channel is closed when stdout is, i.e. when the worker exits.
+    responses: Receiver<std::io::Result<WorkerResponse>>,
+}
+impl Worker {
+    fn spawn(mut command: Command) -> std::io::Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+        let (sender, responses) = mpsc::channel();
+        std::thread::spawn(move || loop {
+            let response = match read_frame(&mut stdout) {
+                Ok(Some(status)) => read_required_frame(&mut stdout).map(|data| match status.as_slice() {
+                    b"ok" => Ok(data),
+                    _ => Err(String::from_utf8_lossy(&data).into_owned()),
+                }),
+                Ok(None) => break,
+                Err(e) => Err(e),
+            };
+            let failed = response.is_err();
+            if sender.send(response).is_err() || failed {
+                break;
+            }
+        });
+
+        Ok(Self {
+            child,
+            stdin,
+            responses,
+        })
+    }
+
+    fn send_request(&mut self, dxbc: &[u8], target: AsicTarget) -> std::io::Result<()> {
+        write_frame(&mut self.stdin, target.to_str().as_bytes())?;
+        write_frame(&mut self.stdin, dxbc)?;
+        self.stdin.flush()
+    }
+
+    /// Waits briefly for a worker which has stopped responding to exit, describing how it did so.
+    /// If it's still running after that it gets killed.
+    fn exit_status(&mut self) -> String {
+        for _ in 0..100 {
+            match self.child.try_wait() {
+                Ok(Some(status)) => return status.to_string(),
+                Ok(None) => std::thread::sleep(Duration::from_millis(10)),
+                Err(e) => return format!("unknown exit status: {e}"),
+            }
+        }
+        let _ = self.child.kill();
+        match self.child.wait() {
+            Ok(status) => format!("stopped responding, killed ({status})"),
+            Err(e) => format!("unknown exit status: {e}"),
+        }
+    }
+}
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Runs compilations in a worker process, restarting it whenever it crashes or times out
+pub struct Supervisor {
+    /// Creates the command which starts a worker. Its stdin and stdout are replaced with pipes.
+    command: Box<dyn Fn() -> Command + Send + Sync>,
+    timeout: Duration,
+    worker: Mutex<Option<Worker>>,
+}
+impl Supervisor {
+    /// Creates a supervisor for workers started with `program args...`.
+    /// The first worker isn't started until the first compilation.
+    pub fn new(program: impl Into<PathBuf>, args: Vec<String>, timeout: Duration) -> Self {
+        let program = program.into();
+        Self::with_command(
+            move || {
+                let mut command = Command::new(&program);
+                command.args(&args);
+                command
+            },
+            timeout,
+        )
+    }
+
+    /// Creates a supervisor for workers started by the commands `command` returns,
+    /// for when the worker needs more configuration than its arguments (e.g. its environment)
+    pub fn with_command(command: impl Fn() -> Command + Send + Sync + 'static, timeout: Duration) -> Self {
+        Self {
+            command: Box::new(command),
+            timeout,
+            worker: Mutex::new(None),
+        }
+    }
+}
+impl CompileBackend for Supervisor {
+    fn compile_elf(&self, dxbc: &[u8], target: AsicTarget) -> Result<Vec<u8>, CompileError> {
+        let mut worker_slot = self.worker.lock().unwrap();
+        let worker = match worker_slot.as_mut() {
+            Some(worker) => worker,
+            None => worker_slot.insert(Worker::spawn((self.command)()).map_err(CompileError::WorkerIo)?),
+        };
+
+        // If the request can't be sent the worker has died, which is reported the same way as dying during the compilation
+        let response = match worker.send_request(dxbc, target) {
+            Ok(()) => worker.responses.recv_timeout(self.timeout),
+            Err(_) => Err(RecvTimeoutError::Disconnected),
+        };
+        match response {
+            Ok(Ok(Ok(elf))) => Ok(elf),
+            Ok(Ok(Err(message))) => Err(CompileError::WorkerFailed { message }),
+            Ok(Err(_)) | Err(RecvTimeoutError::Disconnected) => {
+                let status = worker.exit_status();
+                *worker_slot = None;
+                Err(CompileError::WorkerCrashed { status })
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                *worker_slot = None;
+                Err(CompileError::WorkerTimedOut { timeout: self.timeout })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Set in the environment of the test binary when it's re-run as a worker by [supervisor], and never in the test runner
+    const WORKER_ENV: &str = "YK_FXO_DISASM_MOCK_WORKER";
+
+    /// Compiles by echoing the DXBC back, unless it's a command to misbehave
+    struct MockBackend;
+    impl CompileBackend for MockBackend {
+        fn compile_elf(&self, dxbc: &[u8], _target: AsicTarget) -> Result<Vec<u8>, CompileError> {
+            match dxbc {
+                b"abort" => std::process::abort(),
+                b"sleep" => {
+                    std::thread::sleep(Duration::from_secs(30));
+                    Ok(vec![])
+                }
+                b"fail" => Err(CompileError::EmptySection { name: ".text" }),
+                _ => Ok(dxbc.to_vec()),
+            }
+        }
+    }
+
+    /// Not a real test: when [WORKER_ENV] is set, this is the entry point of the mock worker process
+    #[test]
+    fn mock_worker_main() {
+        if std::env::var_os(WORKER_ENV).is_none() {
+            return;
+        }
+        let res = run_worker(&MockBackend, std::io::stdin().lock(), std::io::stdout().lock());
+        std::process::exit(if res.is_ok() { 0 } else { 1 });
+    }
+
+    /// Starts workers by re-running this test binary with only [mock_worker_main]
+    fn supervisor(timeout: Duration) -> Supervisor {
+        Supervisor::with_command(
+            || {
+                let mut command = Command::new(std::env::current_exe().unwrap());
+                command
+                    .args(["worker::tests::mock_worker_main", "--exact", "--test-threads=1"])
+                    .env(WORKER_ENV, "1");
+                command
+            },
+            timeout,
+        )
+    }
+
+    #[test]
+    fn frames_skip_stray_output() {
+        let mut stream = b"stray output".to_vec();
+        write_frame(&mut stream, b"first").unwrap();
+        stream.extend_from_slice(b"YKC");
+        write_frame(&mut stream, b"").unwrap();
+
+        let mut reader = stream.as_slice();
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(b"first".to_vec()));
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(vec![]));
+        assert_eq!(read_frame(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn worker_compiles_and_reports_errors() {
+        let supervisor = supervisor(Duration::from_secs(30));
+        assert_eq!(supervisor.compile_elf(b"dxbc", AsicTarget::RDNA2).unwrap(), b"dxbc");
+        match supervisor.compile_elf(b"fail", AsicTarget::GCN) {
+            Err(CompileError::WorkerFailed { message }) => assert!(message.contains(".text")),
+            res => panic!("expected WorkerFailed, got {res:?}"),
+        }
+        // Errors don't restart the worker
+        assert_eq!(supervisor.compile_elf(b"again", AsicTarget::RDNA3).unwrap(), b"again");
+    }
+
+    #[test]
+    fn worker_restarts_after_crash() {
+        let supervisor = supervisor(Duration::from_secs(30));
+        assert!(matches!(
+            supervisor.compile_elf(b"abort", AsicTarget::RDNA2),
+            Err(CompileError::WorkerCrashed { .. })
+        ));
+        assert_eq!(supervisor.compile_elf(b"dxbc", AsicTarget::RDNA2).unwrap(), b"dxbc");
+    }
+
+    #[test]
+    fn worker_restarts_after_timeout() {
+        let supervisor = supervisor(Duration::from_secs(2));
+        // Give the first worker time to start, so the timeout only covers the compilation
+        assert_eq!(supervisor.compile_elf(b"warmup", AsicTarget::RDNA2).unwrap(), b"warmup");
+        assert!(matches!(
+            supervisor.compile_elf(b"sleep", AsicTarget::RDNA2),
+            Err(CompileError::WorkerTimedOut { .. })
+        ));
+        assert_eq!(supervisor.compile_elf(b"dxbc", AsicTarget::RDNA2).unwrap(), b"dxbc");
+    }
+}
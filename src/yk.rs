@@ -6,6 +6,11 @@ use nom::{
     IResult,
 };
 
+use crate::{
+    db::ShaderStage,
+    dxbc::{program_version, ProgramVersion},
+};
+
 #[derive(Debug, PartialEq)]
 pub enum YkGfxError<I> {
     Nom(I, ErrorKind),
@@ -21,20 +26,196 @@ impl<I> ParseError<I> for YkGfxError<I> {
     }
 }
 
-pub struct GSVS<'a> {
-    pub dxbc: &'a [u8],
+/// The magic for each kind of single-shader container, and the stage it claims to hold.
+///
+/// These are the only magics observed in shipped games.
+/// Other stages are identified by the program version token of the DXBC inside, see [YkShader::stage].
+const STAGE_MAGICS: [(&[u8; 4], ShaderStage); 2] = [
+    (b"GSVS", ShaderStage::Vertex),
+    (b"GSPS", ShaderStage::Fragment),
+];
+
+/// The shader models the Dragon Engine's D3D11 renderer is expected to use.
+/// The containers don't record a shader model themselves.
+const EXPECTED_SHADER_MODELS: [(u8, u8); 3] = [(4, 0), (4, 1), (5, 0)];
+
+/// A discrepancy between a container and the DXBC inside it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerWarning {
+    /// The DXBC doesn't have a readable program version token, so the stage couldn't be confirmed
+    MissingProgramVersion,
+    /// The container magic claims a different stage to the DXBC program type token
+    StageMismatch {
+        container_stage: ShaderStage,
+        program_stage: ShaderStage,
+    },
+    /// The DXBC uses a shader model outside of [EXPECTED_SHADER_MODELS]
+    UnexpectedShaderModel { major: u8, minor: u8 },
+}
+impl std::fmt::Display for ContainerWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContainerWarning::MissingProgramVersion => {
+                write!(f, "DXBC has no program version token")
+            }
+            ContainerWarning::StageMismatch {
+                container_stage,
+                program_stage,
+            } => write!(
+                f,
+                "container claims {} shader, but DXBC is a {} shader",
+                container_stage.to_str(),
+                program_stage.to_str()
+            ),
+            ContainerWarning::UnexpectedShaderModel { major, minor } => {
+                write!(f, "DXBC uses unexpected shader model {major}.{minor}")
+            }
+        }
+    }
 }
 
-pub struct GSPS<'a> {
+/// A single shader inside a container
+pub struct YkShader<'a> {
+    /// The stage claimed by the container magic
+    pub container_stage: ShaderStage,
+    /// The version token of the DXBC program, if it could be read
+    pub program: Option<ProgramVersion>,
     pub dxbc: &'a [u8],
 }
+impl<'a> YkShader<'a> {
+    /// The stage of the shader, taken from the DXBC where possible and the container otherwise
+    pub fn stage(&self) -> ShaderStage {
+        self.program
+            .map_or(self.container_stage, |program| program.stage)
+    }
+
+    /// Returns every discrepancy between the container and the DXBC
+    pub fn warnings(&self) -> Vec<ContainerWarning> {
+        let Some(program) = self.program else {
+            return vec![ContainerWarning::MissingProgramVersion];
+        };
+
+        let mut warnings = vec![];
+        if program.stage != self.container_stage {
+            warnings.push(ContainerWarning::StageMismatch {
+                container_stage: self.container_stage,
+                program_stage: program.stage,
+            });
+        }
+        if !EXPECTED_SHADER_MODELS.contains(&(program.major, program.minor)) {
+            warnings.push(ContainerWarning::UnexpectedShaderModel {
+                major: program.major,
+                minor: program.minor,
+            });
+        }
+        warnings
+    }
+}
+
+/// The kinds of shader container, identified by their magic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum YkContainerKind {
+    /// `.fxo` files, containing a vertex and/or pixel shader
+    GSFX,
+    /// Standalone single-stage containers, i.e. legacy `.vso` (GSVS) and `.pso` (GSPS) files
+    Standalone(ShaderStage),
+}
+impl YkContainerKind {
+    /// Identifies the kind of container from its magic, or returns None if it isn't a shader container
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        let magic = bytes.get(0..4)?;
+        if magic == b"GSFX" {
+            return Some(YkContainerKind::GSFX);
+        }
+        STAGE_MAGICS
+            .iter()
+            .find(|(stage_magic, _)| magic == stage_magic.as_slice())
+            .map(|(_, stage)| YkContainerKind::Standalone(*stage))
+    }
+}
+
+/// A shader container of any kind
+pub struct YkContainer<'a> {
+    pub kind: YkContainerKind,
+    pub shaders: Vec<YkShader<'a>>,
+}
+impl<'a> YkContainer<'a> {
+    /// Returns the DXBC for each shader stage in the container
+    pub fn stages(&self) -> Vec<(ShaderStage, &'a [u8])> {
+        self.shaders
+            .iter()
+            .map(|shader| (shader.stage(), shader.dxbc))
+            .collect()
+    }
+
+    /// Returns the warnings for every shader in the container, prefixed with the stage claimed by the container
+    pub fn warnings(&self) -> Vec<String> {
+        self.shaders
+            .iter()
+            .flat_map(|shader| {
+                shader
+                    .warnings()
+                    .into_iter()
+                    .map(|warning| format!("{}: {warning}", shader.container_stage.to_str()))
+            })
+            .collect()
+    }
+}
+
+/// Returns `length` bytes of `overall` starting at `start`, as given by an (offset, length) pair in a header.
+/// If they're out of bounds, the error points at `input`, where the pair was read.
+fn header_slice<'a>(
+    overall: &'a [u8],
+    start: u32,
+    length: u32,
+    input: &'a [u8],
+) -> Result<&'a [u8], nom::Err<YkGfxError<&'a [u8]>>> {
+    let (start, length) = (start as usize, length as usize);
+    start
+        .checked_add(length)
+        .and_then(|end| overall.get(start..end))
+        .ok_or(nom::Err::Error(YkGfxError::Nom(input, ErrorKind::Eof)))
+}
+
+/// Parses any kind of shader container, picking the parser based on the magic
+pub fn parse_container<'a>(
+    overall: &'a [u8],
+) -> IResult<&'a [u8], YkContainer<'a>, YkGfxError<&'a [u8]>> {
+    match YkContainerKind::detect(overall) {
+        Some(YkContainerKind::GSFX) => {
+            let (input, shaders) = parse_gsfx(overall)?;
+            Ok((
+                input,
+                YkContainer {
+                    kind: YkContainerKind::GSFX,
+                    shaders,
+                },
+            ))
+        }
+        Some(kind @ YkContainerKind::Standalone(_)) => {
+            let (input, shader) = parse_gs_shader(overall)?;
+            Ok((
+                input,
+                YkContainer {
+                    kind,
+                    shaders: vec![shader],
+                },
+            ))
+        }
+        None => Err(nom::Err::Error(YkGfxError::Nom(overall, ErrorKind::Tag))),
+    }
+}
 
 /// Reads the GSFX header
 ///
-/// returns (overall sans GSFX header, (GSVS, GSPS))
+/// The header has a slot (offset, length) for the vertex and pixel shaders, and no others have been observed.
+/// Each slot holds a GSVS/GSPS container, and empty slots are skipped so containers without one of them are handled.
+/// The stage of each shader is taken from its DXBC, so a slot holding e.g. a geometry shader is reported as such.
+///
+/// returns (overall sans GSFX header, shaders)
 pub fn parse_gsfx<'a>(
     overall: &'a [u8],
-) -> IResult<&'a [u8], (GSVS<'a>, GSPS<'a>), YkGfxError<&'a [u8]>> {
+) -> IResult<&'a [u8], Vec<YkShader<'a>>, YkGfxError<&'a [u8]>> {
     let (input, (_magic, _unk1, _unk2, _overall_len)) =
         tuple((tag(b"GSFX"), le_u32, le_u32, le_u32))(overall)?;
 
@@ -43,51 +224,42 @@ pub fn parse_gsfx<'a>(
     let (input, (vs_start, vs_length)) = tuple((le_u32, le_u32))(input)?;
     let (input, (fs_start, fs_length)) = tuple((le_u32, le_u32))(input)?;
 
-    let (vs_start, vs_length, fs_start, fs_length) = (
-        vs_start as usize,
-        vs_length as usize,
-        fs_start as usize,
-        fs_length as usize,
-    );
-
-    let (_, gsvs) = parse_gsvs(&overall[vs_start..(vs_start + vs_length)])?;
-    let (_, gsps) = parse_gsps(&overall[fs_start..(fs_start + fs_length)])?;
+    let mut shaders = vec![];
+    for (start, length) in [(vs_start, vs_length), (fs_start, fs_length)] {
+        if length == 0 {
+            continue;
+        }
+        let (_, shader) = parse_gs_shader(header_slice(overall, start, length, input)?)?;
+        shaders.push(shader);
+    }
 
-    Ok((input, (gsvs, gsps)))
+    Ok((input, shaders))
 }
 
-pub fn parse_gsvs<'a>(overall: &'a [u8]) -> IResult<&'a [u8], GSVS<'a>, YkGfxError<&'a [u8]>> {
-    let (input, (_magic, _unk1, _unk2, _dxbc_len)) =
-        tuple((tag(b"GSVS"), le_u32, le_u32, le_u32))(overall)?;
-
-    let (input, (_unk3, _unk4, dxbc_offset, dxbc_len)) =
-        tuple((le_u32, le_u32, le_u32, le_u32))(input)?;
-
-    let dxbc_offset = dxbc_offset as usize;
-    let dxbc_len = dxbc_len as usize;
-
-    Ok((
-        input,
-        GSVS {
-            dxbc: &overall[dxbc_offset..(dxbc_offset + dxbc_len)],
-        },
-    ))
-}
+/// Reads a single-shader container (GSVS, GSPS etc.) and the version token of the DXBC inside it
+pub fn parse_gs_shader<'a>(overall: &'a [u8]) -> IResult<&'a [u8], YkShader<'a>, YkGfxError<&'a [u8]>> {
+    let (input, magic) = take(4_usize)(overall)?;
+    let container_stage = match STAGE_MAGICS
+        .iter()
+        .find(|(stage_magic, _)| magic == stage_magic.as_slice())
+    {
+        Some((_, stage)) => *stage,
+        None => return Err(nom::Err::Error(YkGfxError::Nom(overall, ErrorKind::Tag))),
+    };
 
-pub fn parse_gsps<'a>(overall: &'a [u8]) -> IResult<&'a [u8], GSPS<'a>, YkGfxError<&'a [u8]>> {
-    let (input, (_magic, _unk1, _unk2, _dxbc_len)) =
-        tuple((tag(b"GSPS"), le_u32, le_u32, le_u32))(overall)?;
+    let (input, (_unk1, _unk2, _dxbc_len)) = tuple((le_u32, le_u32, le_u32))(input)?;
 
     let (input, (_unk3, _unk4, dxbc_offset, dxbc_len)) =
         tuple((le_u32, le_u32, le_u32, le_u32))(input)?;
 
-    let dxbc_offset = dxbc_offset as usize;
-    let dxbc_len = dxbc_len as usize;
+    let dxbc = header_slice(overall, dxbc_offset, dxbc_len, input)?;
 
     Ok((
         input,
-        GSPS {
-            dxbc: &overall[dxbc_offset..(dxbc_offset + dxbc_len)],
+        YkShader {
+            container_stage,
+            program: program_version(dxbc),
+            dxbc,
         },
     ))
 }
@@ -0,0 +1,64 @@
+#version 450
+// Fragment shader decompiled by yk_fxo_disasm
+
+float saturate(float x) { return clamp(x, 0.0, 1.0); }
+float rcp(float x) { return 1.0 / x; }
+vec2 saturate(vec2 x) { return clamp(x, 0.0, 1.0); }
+vec2 rcp(vec2 x) { return 1.0 / x; }
+vec3 saturate(vec3 x) { return clamp(x, 0.0, 1.0); }
+vec3 rcp(vec3 x) { return 1.0 / x; }
+vec4 saturate(vec4 x) { return clamp(x, 0.0, 1.0); }
+vec4 rcp(vec4 x) { return 1.0 / x; }
+float asfloat(float x) { return x; }
+float asfloat(int x) { return intBitsToFloat(x); }
+float asfloat(uint x) { return uintBitsToFloat(x); }
+int asint(float x) { return floatBitsToInt(x); }
+uint asuint(float x) { return floatBitsToUint(x); }
+vec2 asfloat(vec2 x) { return x; }
+vec2 asfloat(ivec2 x) { return intBitsToFloat(x); }
+vec2 asfloat(uvec2 x) { return uintBitsToFloat(x); }
+ivec2 asint(vec2 x) { return floatBitsToInt(x); }
+uvec2 asuint(vec2 x) { return floatBitsToUint(x); }
+vec3 asfloat(vec3 x) { return x; }
+vec3 asfloat(ivec3 x) { return intBitsToFloat(x); }
+vec3 asfloat(uvec3 x) { return uintBitsToFloat(x); }
+ivec3 asint(vec3 x) { return floatBitsToInt(x); }
+uvec3 asuint(vec3 x) { return floatBitsToUint(x); }
+vec4 asfloat(vec4 x) { return x; }
+vec4 asfloat(ivec4 x) { return intBitsToFloat(x); }
+vec4 asfloat(uvec4 x) { return uintBitsToFloat(x); }
+ivec4 asint(vec4 x) { return floatBitsToInt(x); }
+uvec4 asuint(vec4 x) { return floatBitsToUint(x); }
+
+// s0: sampler g_LinearWrap, combined into the textures it's used with
+layout(binding = 3) uniform sampler2D t3; // g_Albedo
+
+layout(location = 1) in vec2 in_TEXCOORD0;
+layout(location = 2, component = 2) flat in uint in_TEXCOORD1;
+layout(location = 0) out vec4 out_SV_Target0;
+layout(location = 1) out uint out_SV_Target1;
+
+void main() {
+    vec4 v0 = vec4(0.0);
+    vec4 v1 = vec4(0.0);
+    vec4 v2 = vec4(0.0);
+    v0.xyzw = gl_FragCoord.xyzw;
+    v1.xy = asfloat(in_TEXCOORD0);
+    v1.z = asfloat(in_TEXCOORD1);
+    v2.x = asfloat(gl_FrontFacing ? 0xFFFFFFFFu : 0u);
+    vec4 o0 = vec4(0.0);
+    vec4 o1 = vec4(0.0);
+    vec4 oSV_Depth = vec4(0.0);
+
+    vec4 albedo = texture(t3, v1.xy);
+    if (v2.x == 0) {
+    albedo.xyz = fract(albedo.xyz * inversesqrt(v0.w));
+    }
+    o0 = albedo;
+    o1.x = asfloat(asuint(v1.z) + 1u);
+    oSV_Depth.x = saturate(v0.z);
+
+    out_SV_Target0 = o0.xyzw;
+    out_SV_Target1 = asuint(o1.x);
+    gl_FragDepth = oSV_Depth.x;
+}
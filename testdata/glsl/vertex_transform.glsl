@@ -0,0 +1,66 @@
+#version 450
+// Vertex shader decompiled by yk_fxo_disasm
+
+float saturate(float x) { return clamp(x, 0.0, 1.0); }
+float rcp(float x) { return 1.0 / x; }
+vec2 saturate(vec2 x) { return clamp(x, 0.0, 1.0); }
+vec2 rcp(vec2 x) { return 1.0 / x; }
+vec3 saturate(vec3 x) { return clamp(x, 0.0, 1.0); }
+vec3 rcp(vec3 x) { return 1.0 / x; }
+vec4 saturate(vec4 x) { return clamp(x, 0.0, 1.0); }
+vec4 rcp(vec4 x) { return 1.0 / x; }
+float asfloat(float x) { return x; }
+float asfloat(int x) { return intBitsToFloat(x); }
+float asfloat(uint x) { return uintBitsToFloat(x); }
+int asint(float x) { return floatBitsToInt(x); }
+uint asuint(float x) { return floatBitsToUint(x); }
+vec2 asfloat(vec2 x) { return x; }
+vec2 asfloat(ivec2 x) { return intBitsToFloat(x); }
+vec2 asfloat(uvec2 x) { return uintBitsToFloat(x); }
+ivec2 asint(vec2 x) { return floatBitsToInt(x); }
+uvec2 asuint(vec2 x) { return floatBitsToUint(x); }
+vec3 asfloat(vec3 x) { return x; }
+vec3 asfloat(ivec3 x) { return intBitsToFloat(x); }
+vec3 asfloat(uvec3 x) { return uintBitsToFloat(x); }
+ivec3 asint(vec3 x) { return floatBitsToInt(x); }
+uvec3 asuint(vec3 x) { return floatBitsToUint(x); }
+vec4 asfloat(vec4 x) { return x; }
+vec4 asfloat(ivec4 x) { return intBitsToFloat(x); }
+vec4 asfloat(uvec4 x) { return uintBitsToFloat(x); }
+ivec4 asint(vec4 x) { return floatBitsToInt(x); }
+uvec4 asuint(vec4 x) { return floatBitsToUint(x); }
+
+layout(std140, binding = 1) uniform Transform {
+    vec4 cb1[5];
+    // cb1[0..3]: layout(row_major) mat4 g_WorldViewProj
+    // cb1[4].xy: vec2 g_UVScale
+};
+
+layout(location = 0) in vec3 in_POSITION0;
+layout(location = 1) in vec2 in_TEXCOORD0;
+layout(location = 1, component = 2) in vec2 in_TEXCOORD1;
+layout(location = 1) out vec2 out_TEXCOORD0;
+layout(location = 2, component = 2) out uint out_TEXCOORD1;
+
+void main() {
+    vec4 v0 = vec4(0.0);
+    vec4 v1 = vec4(0.0);
+    vec4 v2 = vec4(0.0);
+    v0.xyz = asfloat(in_POSITION0);
+    v1.xy = asfloat(in_TEXCOORD0);
+    v1.zw = asfloat(in_TEXCOORD1);
+    v2.x = asfloat(gl_VertexID);
+    vec4 o0 = vec4(0.0);
+    vec4 o1 = vec4(0.0);
+
+    o0.x = dot(vec4(v0.xyz, 1.0f), cb1[0]);
+    o0.y = dot(vec4(v0.xyz, 1.0f), cb1[1]);
+    o0.z = dot(vec4(v0.xyz, 1.0f), cb1[2]);
+    o0.w = dot(vec4(v0.xyz, 1.0f), cb1[3]);
+    o1.xy = mix(v1.xy, v1.zw, saturate(cb1[4].x)) * cb1[4].xy;
+    o1.z = v2.x;
+
+    gl_Position = o0.xyzw;
+    out_TEXCOORD0 = o1.xy;
+    out_TEXCOORD1 = asuint(o1.z);
+}
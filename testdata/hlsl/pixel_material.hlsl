@@ -0,0 +1,36 @@
+// Fragment shader decompiled by yk_fxo_disasm
+
+cbuffer Material : register(b1) {
+    float4 cb1[1];
+    // cb1[0].xyz: float3 g_Tint
+    // cb1[0].w: float g_Alpha
+};
+
+SamplerState s0 : register(s0); // g_LinearWrap
+Texture2D<float4> t2 : register(t2); // g_Albedo
+
+struct PS_INPUT {
+    float4 SV_Position0 : SV_Position0;
+    float2 TEXCOORD0 : TEXCOORD0;
+    uint SV_IsFrontFace0 : SV_IsFrontFace0;
+};
+
+struct PS_OUTPUT {
+    float4 SV_Target0 : SV_Target0;
+};
+
+void main(in PS_INPUT input, out PS_OUTPUT output) {
+    float4 v0 = 0;
+    float4 v1 = 0;
+    float4 v2 = 0;
+    v0.xyzw = asfloat(input.SV_Position0);
+    v1.xy = asfloat(input.TEXCOORD0);
+    v2.x = asfloat(input.SV_IsFrontFace0);
+    float4 o0 = 0;
+
+    float4 albedo = t2.Sample(s0, v1.xy);
+    o0.xyz = albedo.xyz * cb1[0].xyz;
+    o0.w = v2.x ? cb1[0].w : 0.0f;
+
+    output.SV_Target0 = o0.xyzw;
+}